@@ -1,51 +1,199 @@
-extern crate overload;
-
-use overload::overload;
+use num_traits::Float;
 use std::ops;
 
-use statrs::function::erf::erfc;
-use std::f64::consts::PI;
-use std::f64::INFINITY;
+use statrs::function::erf::{erf, erfc, erfc_inv};
+use std::f64::consts::{PI, SQRT_2};
+
+#[cfg(feature = "rand")]
+use rand::distributions::{Distribution, Open01};
+#[cfg(feature = "rand")]
+use rand::Rng;
 
-const PREC: f64 = 1e-4;
+pub mod natural;
 
+/// Normal distribution with density proportional to `exp(-((x - mu) / sigma)^2)`,
+/// so `sigma` is sqrt(2) times the standard deviation (see math/indicators.pdf)
+///
+/// Generic over the float type so that large rating tables may be stored in `f32`,
+/// while everything beyond arithmetic is implemented for `f64` only.
 #[derive(Clone, Debug)]
-pub struct Gaussian {
-    pub mu: f64,
-    pub sigma: f64,
+pub struct Gaussian<T = f64> {
+    pub mu: T,
+    pub sigma: T,
 }
 
 #[allow(unused)]
 pub const ZERO: Gaussian = Gaussian { mu: 0., sigma: 0. };
 #[allow(unused)]
-pub const ONE: Gaussian = Gaussian { mu: 0., sigma: INFINITY };
+pub const ONE: Gaussian = Gaussian { mu: 0., sigma: f64::INFINITY };
+
+
+/// `overload!` counterpart generic over the float type, supports only the forms used below
+macro_rules! overload_float {
+    (($a:ident : ?Gaussian) + ($b:ident : ?$rhs:ident) -> Gaussian $body:block) => {
+        overload_float!(@binary Add, add, $a, $b, $rhs, $body);
+    };
+    (($a:ident : ?Gaussian) - ($b:ident : ?$rhs:ident) -> Gaussian $body:block) => {
+        overload_float!(@binary Sub, sub, $a, $b, $rhs, $body);
+    };
+    (($a:ident : ?Gaussian) * ($b:ident : ?$rhs:ident) -> Gaussian $body:block) => {
+        overload_float!(@binary Mul, mul, $a, $b, $rhs, $body);
+    };
+    (($a:ident : ?Gaussian) / ($b:ident : ?$rhs:ident) -> Gaussian $body:block) => {
+        overload_float!(@binary Div, div, $a, $b, $rhs, $body);
+    };
+    (($a:ident : &mut Gaussian) += ($b:ident : ?$rhs:ident) $body:block) => {
+        overload_float!(@assign AddAssign, add_assign, $a, $b, $rhs, $body);
+    };
+    (($a:ident : &mut Gaussian) -= ($b:ident : ?$rhs:ident) $body:block) => {
+        overload_float!(@assign SubAssign, sub_assign, $a, $b, $rhs, $body);
+    };
+    (($a:ident : &mut Gaussian) *= ($b:ident : ?$rhs:ident) $body:block) => {
+        overload_float!(@assign MulAssign, mul_assign, $a, $b, $rhs, $body);
+    };
+    (($a:ident : &mut Gaussian) /= ($b:ident : ?$rhs:ident) $body:block) => {
+        overload_float!(@assign DivAssign, div_assign, $a, $b, $rhs, $body);
+    };
+    (-($a:ident : ?Gaussian) -> Gaussian $body:block) => {
+        impl<T: Float> ops::Neg for Gaussian<T> {
+            type Output = Gaussian<T>;
+            fn neg(self) -> Gaussian<T> {
+                let $a = &self;
+                $body
+            }
+        }
+
+        impl<'a, T: Float> ops::Neg for &'a Gaussian<T> {
+            type Output = Gaussian<T>;
+            fn neg(self) -> Gaussian<T> {
+                let $a = self;
+                $body
+            }
+        }
+    };
+
+    (@binary $op_trait:ident, $op_fn:ident, $a:ident, $b:ident, Gaussian, $body:block) => {
+        impl<T: Float> ops::$op_trait<Gaussian<T>> for Gaussian<T> {
+            type Output = Gaussian<T>;
+            fn $op_fn(self, $b: Gaussian<T>) -> Gaussian<T> {
+                let ($a, $b) = (&self, &$b);
+                $body
+            }
+        }
+
+        impl<'b, T: Float> ops::$op_trait<&'b Gaussian<T>> for Gaussian<T> {
+            type Output = Gaussian<T>;
+            fn $op_fn(self, $b: &'b Gaussian<T>) -> Gaussian<T> {
+                let $a = &self;
+                $body
+            }
+        }
+
+        impl<'a, T: Float> ops::$op_trait<Gaussian<T>> for &'a Gaussian<T> {
+            type Output = Gaussian<T>;
+            fn $op_fn(self, $b: Gaussian<T>) -> Gaussian<T> {
+                let ($a, $b) = (self, &$b);
+                $body
+            }
+        }
+
+        impl<'a, 'b, T: Float> ops::$op_trait<&'b Gaussian<T>> for &'a Gaussian<T> {
+            type Output = Gaussian<T>;
+            fn $op_fn(self, $b: &'b Gaussian<T>) -> Gaussian<T> {
+                let $a = self;
+                $body
+            }
+        }
+    };
+    (@binary $op_trait:ident, $op_fn:ident, $a:ident, $b:ident, T, $body:block) => {
+        impl<T: Float> ops::$op_trait<T> for Gaussian<T> {
+            type Output = Gaussian<T>;
+            fn $op_fn(self, $b: T) -> Gaussian<T> {
+                let $a = &self;
+                $body
+            }
+        }
+
+        impl<'b, T: Float> ops::$op_trait<&'b T> for Gaussian<T> {
+            type Output = Gaussian<T>;
+            fn $op_fn(self, $b: &'b T) -> Gaussian<T> {
+                let ($a, $b) = (&self, *$b);
+                $body
+            }
+        }
+
+        impl<'a, T: Float> ops::$op_trait<T> for &'a Gaussian<T> {
+            type Output = Gaussian<T>;
+            fn $op_fn(self, $b: T) -> Gaussian<T> {
+                let $a = self;
+                $body
+            }
+        }
+
+        impl<'a, 'b, T: Float> ops::$op_trait<&'b T> for &'a Gaussian<T> {
+            type Output = Gaussian<T>;
+            fn $op_fn(self, $b: &'b T) -> Gaussian<T> {
+                let ($a, $b) = (self, *$b);
+                $body
+            }
+        }
+    };
+    (@assign $op_trait:ident, $op_fn:ident, $a:ident, $b:ident, Gaussian, $body:block) => {
+        impl<T: Float> ops::$op_trait<Gaussian<T>> for Gaussian<T> {
+            fn $op_fn(&mut self, $b: Gaussian<T>) {
+                let ($a, $b) = (self, &$b);
+                $body
+            }
+        }
+
+        impl<'b, T: Float> ops::$op_trait<&'b Gaussian<T>> for Gaussian<T> {
+            fn $op_fn(&mut self, $b: &'b Gaussian<T>) {
+                let $a = self;
+                $body
+            }
+        }
+    };
+    (@assign $op_trait:ident, $op_fn:ident, $a:ident, $b:ident, T, $body:block) => {
+        impl<T: Float> ops::$op_trait<T> for Gaussian<T> {
+            fn $op_fn(&mut self, $b: T) {
+                let $a = self;
+                $body
+            }
+        }
+
+        impl<'b, T: Float> ops::$op_trait<&'b T> for Gaussian<T> {
+            fn $op_fn(&mut self, $b: &'b T) {
+                let ($a, $b) = (self, *$b);
+                $body
+            }
+        }
+    };
+}
 
 
-overload!((a: ?Gaussian) + (b: ?Gaussian) -> Gaussian {
+overload_float!((a: ?Gaussian) + (b: ?Gaussian) -> Gaussian {
     Gaussian {
         mu: a.mu + b.mu,
         sigma: (a.sigma.powi(2) + b.sigma.powi(2)).sqrt(),
     }
 });
 
-overload!((a: &mut Gaussian) += (b: ?Gaussian) {
-    a.mu += b.mu;
-    a.sigma = (a.sigma.powi(2) + b.sigma.powi(2)).sqrt();
+overload_float!((a: &mut Gaussian) += (b: ?Gaussian) {
+    *a = &*a + b;
 });
 
-overload!((a: ?Gaussian) - (b: ?Gaussian) -> Gaussian {
+overload_float!((a: ?Gaussian) - (b: ?Gaussian) -> Gaussian {
     Gaussian {
         mu: a.mu - b.mu,
         sigma: (a.sigma.powi(2) + b.sigma.powi(2)).sqrt(),
     }
 });
 
-overload!((a: &mut Gaussian) -= (b: ?Gaussian) {
-    a.mu -= b.mu;
-    a.sigma = (a.sigma.powi(2) + b.sigma.powi(2)).sqrt();
+overload_float!((a: &mut Gaussian) -= (b: ?Gaussian) {
+    *a = &*a - b;
 });
 
-overload!(-(a: &mut Gaussian) -> Gaussian {
+overload_float!(-(a: ?Gaussian) -> Gaussian {
     Gaussian {
         mu: -a.mu,
         sigma: a.sigma,
@@ -53,32 +201,30 @@ overload!(-(a: &mut Gaussian) -> Gaussian {
 });
 
 
-overload!((a: ?Gaussian) * (b: ?f64) -> Gaussian {
+overload_float!((a: ?Gaussian) * (b: ?T) -> Gaussian {
     Gaussian {
         mu: a.mu * b,
         sigma: a.sigma * b.abs(),
     }
 });
 
-overload!((a: &mut Gaussian) *= (b: ?f64) {
-    a.mu *= b;
-    a.sigma *= b.abs();
+overload_float!((a: &mut Gaussian) *= (b: ?T) {
+    *a = &*a * b;
 });
 
-overload!((a: ?Gaussian) / (b: ?f64) -> Gaussian {
+overload_float!((a: ?Gaussian) / (b: ?T) -> Gaussian {
     Gaussian {
         mu: a.mu / b,
         sigma: a.sigma / b.abs(),
     }
 });
 
-overload!((a: &mut Gaussian) /= (b: ?f64) {
-    a.mu /= b;
-    a.sigma /= b.abs();
+overload_float!((a: &mut Gaussian) /= (b: ?T) {
+    *a = &*a / b;
 });
 
 
-overload!((a: ?Gaussian) * (b: ?Gaussian) -> Gaussian {
+overload_float!((a: ?Gaussian) * (b: ?Gaussian) -> Gaussian {
     if a.sigma.is_infinite() {
         return b.clone();
     }
@@ -94,11 +240,11 @@ overload!((a: ?Gaussian) * (b: ?Gaussian) -> Gaussian {
     }
 });
 
-overload!((a: &mut Gaussian) *= (b: ?Gaussian) {
-    *a = a.clone() * b;
+overload_float!((a: &mut Gaussian) *= (b: ?Gaussian) {
+    *a = &*a * b;
 });
 
-overload!((a: ?Gaussian) / (b: ?Gaussian) -> Gaussian {
+overload_float!((a: ?Gaussian) / (b: ?Gaussian) -> Gaussian {
     if b.sigma.is_infinite() {
         return a.clone();
     }
@@ -111,74 +257,236 @@ overload!((a: ?Gaussian) / (b: ?Gaussian) -> Gaussian {
     }
 });
 
-overload!((a: &mut Gaussian) /= (b: ?Gaussian) {
-    *a = a.clone() / b;
+overload_float!((a: &mut Gaussian) /= (b: ?Gaussian) {
+    *a = &*a / b;
 });
 
 
-fn gauss_exponent(mu: f64, sigma: f64, t: f64) -> f64 {
-    (-((t - mu) / sigma).powi(2)).exp()
+impl<T: Float> Gaussian<T> {
+    pub fn zero() -> Self {
+        Gaussian { mu: T::zero(), sigma: T::zero() }
+    }
+
+
+    pub fn one() -> Self {
+        Gaussian { mu: T::zero(), sigma: T::infinity() }
+    }
+
+
+    /// Standard deviation, as opposed to `sigma`
+    pub fn std_dev(&self) -> T {
+        self.sigma / (T::one() + T::one()).sqrt()
+    }
+
+
+    /// Converts to another float type, `None` if the values are out of its range
+    pub fn cast<U: Float>(&self) -> Option<Gaussian<U>> {
+        Some(Gaussian {
+            mu: U::from(self.mu)?,
+            sigma: U::from(self.sigma)?,
+        })
+    }
+}
+
+impl From<Gaussian<f32>> for Gaussian<f64> {
+    fn from(gaussian: Gaussian<f32>) -> Self {
+        gaussian.cast().unwrap()
+    }
+}
+
+
+/// Scaled complementary error function `exp(x^2) * erfc(x)`, which stays finite and accurate
+/// far into the right tail where `erfc` itself underflows
+fn erfcx(x: f64) -> f64 {
+    if x < 0. {
+        2. * (x * x).exp() - erfcx(-x)
+    } else if x < 3. {
+        (x * x).exp() * erfc(x)
+    } else {
+        // continued fraction, 40 terms are enough for the full precision when x >= 3
+        let mut denominator = x;
+        for k in (1..=40).rev() {
+            denominator = x + k as f64 / 2. / denominator;
+        }
+        1. / (PI.sqrt() * denominator)
+    }
 }
 
-fn moment0(mu: f64, sigma: f64, t: f64) -> f64 {
-    sigma * PI.sqrt() / 2. * erfc((t - mu) / sigma)
+/// Gauss-Legendre nodes and weights on `[-1, 1]`, symmetric pairs given once
+const GAUSS_LEGENDRE: [(f64, f64); 5] = [
+    (0.148_874_338_981_631_2, 0.295_524_224_714_752_9),
+    (0.433_395_394_129_247_2, 0.269_266_719_309_996_3),
+    (0.679_409_568_299_024_4, 0.219_086_362_515_982),
+    (0.865_063_366_688_984_5, 0.149_451_349_150_580_6),
+    (0.973_906_528_517_171_7, 0.066_671_344_308_688_1),
+];
+
+/// Mean and variance of the standard normal distribution truncated to `[a, b]`,
+/// i.e. TrueSkill v and 1 - w functions
+fn truncated_moments(a: f64, b: f64) -> (f64, f64) {
+    assert!(a <= b);
+
+    if a == b {
+        return (a, 0.);
+    }
+    if (b - a) * f64::max(1., f64::max(a.abs(), b.abs())) <= 1. {
+        return truncated_moments_narrow(a, b);
+    }
+    if b <= 0. {
+        let (v, sigma2) = truncated_moments(-b, -a);
+        return (-v, sigma2);
+    }
+
+    let sqrt_2pi = (2. * PI).sqrt();
+    let v;
+    let x_pdf_diff;
+
+    if a >= 0. {
+        // the whole interval lies in the right tail, so everything is scaled by exp(a^2 / 2)
+        let r = if b.is_infinite() { 0. } else { (-(b - a) * (b + a) / 2.).exp() };
+        let (b_tail, b_r) = if r == 0. { (0., 0.) } else { (r * erfcx(b / SQRT_2), b * r) };
+
+        if a >= 4. && b_r <= 1e-17 * a {
+            return truncated_moments_tail(a);
+        }
+
+        let alpha = sqrt_2pi / 2. * (erfcx(a / SQRT_2) - b_tail);
+
+        v = (1. - r) / alpha;
+        x_pdf_diff = (a - b_r) / alpha;
+    } else {
+        let pdf = |x: f64| if x.is_infinite() { 0. } else { (-x * x / 2.).exp() / sqrt_2pi };
+        let x_pdf = |x: f64| if x.is_infinite() { 0. } else { x * pdf(x) };
+        let alpha = (erf(b / SQRT_2) - erf(a / SQRT_2)) / 2.;
+
+        v = (pdf(a) - pdf(b)) / alpha;
+        x_pdf_diff = (x_pdf(a) - x_pdf(b)) / alpha;
+    }
+
+    (v, f64::max(0., 1. + x_pdf_diff - v.powi(2)))
 }
 
-fn moment1(mu: f64, sigma: f64, t: f64) -> f64 {
-    mu * moment0(0., sigma, t - mu) + sigma.powi(2) / 2. * gauss_exponent(mu, sigma, t)
+/// Same as `truncated_moments` for `[a, inf)` with large `a`, where `1 + a v - v^2` cancels out.
+/// Laplace continued fraction for the Mills ratio gives `v - a` and the variance directly.
+fn truncated_moments_tail(a: f64) -> (f64, f64) {
+    let mut denominator = a;
+    for k in (3..=40).rev() {
+        denominator = a + k as f64 / denominator;
+    }
+    let excess = 1. / denominator;
+    let g = a + 2. * excess;
+
+    (a + 1. / g, (2. * excess * g - 1.) / g.powi(2))
 }
 
-fn moment2(mu: f64, sigma: f64, t: f64) -> f64 {
-    mu.powi(2) * moment0(0., sigma, t - mu) + 2. * mu * moment1(0., sigma, t - mu) +
-        sigma.powi(2) / 4. * (2. * gauss_exponent(mu, sigma, t) * (t - mu) +
-            sigma * PI.sqrt() * erfc((t - mu) / sigma))
+/// Same as `truncated_moments` for short intervals, where the density is almost flat and the
+/// differences of the error functions cancel out, so the moments are integrated directly
+fn truncated_moments_narrow(a: f64, b: f64) -> (f64, f64) {
+    let mid = (a + b) / 2.;
+    let half = (b - a) / 2.;
+    // density relative to its value in the middle of the interval
+    let density = |y: f64| (-y * (2. * mid + y) / 2.).exp();
+
+    let mut moments = [0.; 3];
+
+    for (node, weight) in &GAUSS_LEGENDRE {
+        for y in &[-node * half, node * half] {
+            let mass = weight * density(*y);
+
+            moments[0] += mass;
+            moments[1] += mass * y;
+            moments[2] += mass * y * y;
+        }
+    }
+
+    let shift = moments[1] / moments[0];
+
+    (mid + shift, moments[2] / moments[0] - shift.powi(2))
 }
 
 
 impl Gaussian {
-    pub fn leq_eps(&self, eps: f64) -> Gaussian {
-        assert!(eps >= 0.);
+    fn truncated_posterior(&self, lo: f64, hi: f64) -> Gaussian {
+        // sigma is sqrt(2) times the standard deviation
+        let std = self.sigma / SQRT_2;
+        let (v, sigma2) = truncated_moments((lo - self.mu) / std, (hi - self.mu) / std);
 
-        let alpha = moment0(self.mu, self.sigma, -eps) - moment0(self.mu, self.sigma, eps);
+        let mu = self.mu + std * v;
+        let sigma = self.sigma * sigma2.sqrt();
 
-        if alpha < PREC {
-            return Gaussian { mu: 0., sigma: (1. / 3. as f64).sqrt() } / self;
-        }
+        assert!(!mu.is_nan() && !sigma.is_nan(), "{:?}\tlo {}\thi {}", self, lo, hi);
+
+        Gaussian { mu, sigma }
+    }
+
+
+    fn leq_eps_posterior(&self, eps: f64) -> Gaussian {
+        assert!(eps >= 0.);
+
+        self.truncated_posterior(-eps, eps)
+    }
 
-        let mu = 1. / alpha * (moment1(self.mu, self.sigma, -eps) - moment1(self.mu, self.sigma, eps));
-        let sigma2 = 1. / alpha * (moment2(self.mu, self.sigma, -eps) - moment2(self.mu, self.sigma, eps)) - mu.powi(2);
-        let sigma = sigma2.sqrt();
 
-        assert!(!mu.is_nan() && !sigma.is_nan(), "{:?}\teps {}", self, eps);
+    fn greater_eps_posterior(&self, eps: f64) -> Gaussian {
+        assert!(eps >= 0.);
 
-        let ans = Gaussian { mu, sigma } / self;
+        self.truncated_posterior(eps, f64::INFINITY)
+    }
 
-        assert!(ans.mu.abs() <= eps);
 
-        ans
+    pub fn leq_eps(&self, eps: f64) -> Gaussian {
+        self.leq_eps_posterior(eps) / self
     }
 
 
     pub fn greater_eps(&self, eps: f64) -> Gaussian {
-        assert!(eps >= 0.);
+        self.greater_eps_posterior(eps) / self
+    }
 
-        let alpha = moment0(self.mu, self.sigma, eps);
 
-        if alpha < PREC {
-            return Gaussian { mu: eps, sigma: self.sigma / (2. as f64).sqrt() } / self;
-        }
+    pub fn pdf(&self, x: f64) -> f64 {
+        self.log_pdf(x).exp()
+    }
+
+
+    pub fn log_pdf(&self, x: f64) -> f64 {
+        -((x - self.mu) / self.sigma).powi(2) - (self.sigma * PI.sqrt()).ln()
+    }
+
+
+    pub fn cdf(&self, x: f64) -> f64 {
+        erfc((self.mu - x) / self.sigma) / 2.
+    }
+
+
+    /// Quantile function, the inverse of `cdf`
+    pub fn ppf(&self, p: f64) -> f64 {
+        assert!((0. ..=1.).contains(&p), "Probability {} is out of range", p);
 
-        let mu = 1. / alpha * moment1(self.mu, self.sigma, eps);
-        let sigma2 = 1. / alpha * moment2(self.mu, self.sigma, eps) - mu.powi(2);
-        let sigma = sigma2.sqrt();
+        self.mu - self.sigma * erfc_inv(2. * p)
+    }
+
+
+    /// Kullback-Leibler divergence `KL(self || other)`
+    pub fn kl_divergence(&self, other: &Gaussian) -> f64 {
+        (other.sigma / self.sigma).ln() +
+            (self.sigma.powi(2) + 2. * (self.mu - other.mu).powi(2)) / (2. * other.sigma.powi(2)) - 0.5
+    }
 
-        assert!(!mu.is_nan() && !sigma.is_nan(), "{:?}\teps {}", self, eps);
 
-        let ans = Gaussian { mu, sigma } / self;
+    pub fn hellinger_distance(&self, other: &Gaussian) -> f64 {
+        let ssigma = self.sigma.powi(2) + other.sigma.powi(2);
+        let affinity = (2. * self.sigma * other.sigma / ssigma).sqrt() *
+            (-(self.mu - other.mu).powi(2) / (2. * ssigma)).exp();
 
-        assert!(ans.mu >= 2. * eps);
+        (1. - affinity).max(0.).sqrt()
+    }
+}
 
-        ans
+#[cfg(feature = "rand")]
+impl Distribution<f64> for Gaussian {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        self.ppf(rng.sample(Open01))
     }
 }
 
@@ -0,0 +1,230 @@
+use super::Gaussian;
+
+use overload::overload;
+use std::convert::TryFrom;
+use std::ops;
+
+/// Gaussian in the natural parametrization: precision `pi = 1 / sigma^2` and
+/// precision-adjusted mean `tau = mu / sigma^2`.
+///
+/// Unlike `Gaussian`, it represents improper messages (`pi < 0`) as well as the uniform one
+/// (`pi = 0`), so products and quotients are exact and never need special cases.
+#[derive(Clone, Debug)]
+pub struct GaussianNat {
+    pub pi: f64,
+    pub tau: f64,
+}
+
+/// Point mass at zero, the neutral element of `+`
+#[allow(unused)]
+pub const ZERO: GaussianNat = GaussianNat { pi: f64::INFINITY, tau: 0. };
+/// Uniform message, the neutral element of `*`
+#[allow(unused)]
+pub const ONE: GaussianNat = GaussianNat { pi: 0., tau: 0. };
+
+
+impl GaussianNat {
+    /// Panics on a point mass away from zero: its `tau` would be infinite
+    fn from_mean_variance(mu: f64, sigma2: f64) -> GaussianNat {
+        assert!(sigma2 != 0. || mu == 0., "Point mass at {} has no natural parameters", mu);
+
+        let pi = 1. / sigma2;
+
+        GaussianNat {
+            pi,
+            // avoid 0 * inf for the point mass at zero
+            tau: if mu == 0. { 0. } else { mu * pi },
+        }
+    }
+
+    pub fn is_proper(&self) -> bool {
+        self.pi >= 0.
+    }
+
+    pub fn is_uniform(&self) -> bool {
+        self.pi == 0.
+    }
+
+    pub fn mu(&self) -> f64 {
+        if self.tau == 0. { 0. } else { self.tau / self.pi }
+    }
+
+    /// Variance of the message, negative for improper ones
+    pub fn sigma2(&self) -> f64 {
+        1. / self.pi
+    }
+
+    /// Distribution of `X + offset`, uniform messages stay uniform.
+    /// Panics on shifting `ZERO` by a nonzero offset, see `from`.
+    pub fn shift(&self, offset: f64) -> GaussianNat {
+        if self.is_uniform() || offset == 0. {
+            return self.clone();
+        }
+
+        GaussianNat::from_mean_variance(self.mu() + offset, self.sigma2())
+    }
+
+    /// Same as `Gaussian::leq_eps`, computed without loss of precision on the division.
+    /// Returns `None` if the message is improper and thus has no truncated moments.
+    pub fn leq_eps(&self, eps: f64) -> Option<GaussianNat> {
+        let cavity = Gaussian::try_from(self).ok()?;
+
+        Some(GaussianNat::from(cavity.leq_eps_posterior(eps)) / self)
+    }
+
+    /// Same as `Gaussian::greater_eps`, computed without loss of precision on the division.
+    /// Returns `None` if the message is improper and thus has no truncated moments.
+    pub fn greater_eps(&self, eps: f64) -> Option<GaussianNat> {
+        let cavity = Gaussian::try_from(self).ok()?;
+
+        Some(GaussianNat::from(cavity.greater_eps_posterior(eps)) / self)
+    }
+}
+
+
+/// Panics on `sigma = 0` with `mu != 0`, only the point mass at zero (`ZERO`) is representable
+impl From<&Gaussian> for GaussianNat {
+    fn from(gaussian: &Gaussian) -> Self {
+        GaussianNat::from_mean_variance(gaussian.mu, gaussian.sigma.powi(2))
+    }
+}
+
+impl From<Gaussian> for GaussianNat {
+    fn from(gaussian: Gaussian) -> Self {
+        GaussianNat::from(&gaussian)
+    }
+}
+
+/// Fails for improper messages since `Gaussian` cannot hold a negative variance
+impl TryFrom<&GaussianNat> for Gaussian {
+    type Error = String;
+
+    fn try_from(nat: &GaussianNat) -> Result<Self, Self::Error> {
+        if !nat.is_proper() {
+            return Err(format!("Improper Gaussian {:?}", nat));
+        }
+
+        Ok(Gaussian {
+            mu: nat.mu(),
+            sigma: nat.sigma2().sqrt(),
+        })
+    }
+}
+
+impl TryFrom<GaussianNat> for Gaussian {
+    type Error = String;
+
+    fn try_from(nat: GaussianNat) -> Result<Self, Self::Error> {
+        Gaussian::try_from(&nat)
+    }
+}
+
+
+overload!((a: ?GaussianNat) + (b: ?GaussianNat) -> GaussianNat {
+    if a.is_uniform() || b.is_uniform() {
+        return ONE;
+    }
+
+    GaussianNat::from_mean_variance(a.mu() + b.mu(), a.sigma2() + b.sigma2())
+});
+
+overload!((a: &mut GaussianNat) += (b: ?GaussianNat) {
+    *a = &*a + b;
+});
+
+overload!((a: ?GaussianNat) - (b: ?GaussianNat) -> GaussianNat {
+    if a.is_uniform() || b.is_uniform() {
+        return ONE;
+    }
+
+    GaussianNat::from_mean_variance(a.mu() - b.mu(), a.sigma2() + b.sigma2())
+});
+
+overload!((a: &mut GaussianNat) -= (b: ?GaussianNat) {
+    *a = &*a - b;
+});
+
+overload!(-(a: GaussianNat) -> GaussianNat {
+    -&a
+});
+
+overload!(-(a: &GaussianNat) -> GaussianNat {
+    GaussianNat {
+        pi: a.pi,
+        tau: -a.tau,
+    }
+});
+
+
+// distribution of `b * X`, scaling by `0.` gives the point mass `ZERO`
+overload!((a: ?GaussianNat) * (b: ?f64) -> GaussianNat {
+    if b.eq(&0.) {
+        return ZERO;
+    }
+
+    GaussianNat {
+        pi: a.pi / b.powi(2),
+        tau: a.tau / b,
+    }
+});
+
+overload!((a: &mut GaussianNat) *= (b: ?f64) {
+    if b.eq(&0.) {
+        *a = ZERO;
+        return;
+    }
+
+    a.pi /= b.powi(2);
+    a.tau /= b;
+});
+
+// distribution of `X / b`, dividing by an infinity gives the point mass `ZERO`
+overload!((a: ?GaussianNat) / (b: ?f64) -> GaussianNat {
+    if b.is_infinite() {
+        return ZERO;
+    }
+
+    GaussianNat {
+        pi: a.pi * b.powi(2),
+        tau: a.tau * b,
+    }
+});
+
+overload!((a: &mut GaussianNat) /= (b: ?f64) {
+    if b.is_infinite() {
+        *a = ZERO;
+        return;
+    }
+
+    a.pi *= b.powi(2);
+    a.tau *= b;
+});
+
+
+overload!((a: ?GaussianNat) * (b: ?GaussianNat) -> GaussianNat {
+    GaussianNat {
+        pi: a.pi + b.pi,
+        tau: a.tau + b.tau,
+    }
+});
+
+overload!((a: &mut GaussianNat) *= (b: ?GaussianNat) {
+    a.pi += b.pi;
+    a.tau += b.tau;
+});
+
+overload!((a: ?GaussianNat) / (b: ?GaussianNat) -> GaussianNat {
+    GaussianNat {
+        pi: a.pi - b.pi,
+        tau: a.tau - b.tau,
+    }
+});
+
+overload!((a: &mut GaussianNat) /= (b: ?GaussianNat) {
+    a.pi -= b.pi;
+    a.tau -= b.tau;
+});
+
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,171 @@
+use super::*;
+use super::super::ONE as GAUSSIAN_ONE;
+
+extern crate rand;
+
+use rand::prelude::*;
+
+#[test]
+fn conversion() {
+    let mut rng = rand::thread_rng();
+
+    for _i in 0..10 {
+        let a = Gaussian { mu: rng.gen_range(-1000., 1000.), sigma: rng.gen_range(1., 500.) };
+        let c = Gaussian::try_from(GaussianNat::from(&a)).unwrap();
+
+        assert!(approx_eq!(f64, c.mu, a.mu, epsilon = 1e-9));
+        assert!(approx_eq!(f64, c.sigma, a.sigma, epsilon = 1e-9));
+    }
+}
+
+#[test]
+fn conversion_of_1() {
+    let a = GaussianNat::from(GAUSSIAN_ONE);
+
+    assert!(a.is_uniform());
+    assert_eq!(a.tau, 0.);
+
+    let c = Gaussian::try_from(&ONE).unwrap();
+
+    assert!(c.sigma.is_infinite());
+}
+
+#[test]
+fn muldiv_numerical_stability() {
+    let mut rng = rand::thread_rng();
+
+    for _i in 0..100 {
+        let a = GaussianNat::from(Gaussian { mu: rng.gen(), sigma: rng.gen() });
+        let b = GaussianNat::from(Gaussian { mu: rng.gen(), sigma: rng.gen() });
+        let d = &a * &b;
+        let c = &d / &b;
+
+        // the rounding error is relative to the larger operand
+        let scale = a.pi + b.pi;
+        assert!(approx_eq!(f64, c.pi, a.pi, epsilon = 1e-9 * scale));
+        assert!(approx_eq!(f64, c.tau, a.tau, epsilon = 1e-9 * scale));
+    }
+}
+
+#[test]
+fn divmul_numerical_stability() {
+    let mut rng = rand::thread_rng();
+
+    for _i in 0..100 {
+        let a = GaussianNat::from(Gaussian { mu: rng.gen(), sigma: rng.gen() });
+        let b = GaussianNat::from(Gaussian { mu: rng.gen(), sigma: rng.gen() });
+        let d = &a / &b;
+        let c = &d * &b;
+
+        // the rounding error is relative to the larger operand
+        let scale = a.pi + b.pi;
+        assert!(approx_eq!(f64, c.pi, a.pi, epsilon = 1e-9 * scale));
+        assert!(approx_eq!(f64, c.tau, a.tau, epsilon = 1e-9 * scale));
+    }
+}
+
+#[test]
+fn improper() {
+    let a = GaussianNat::from(Gaussian { mu: 1., sigma: 2. });
+    let b = GaussianNat::from(Gaussian { mu: 3., sigma: 1. });
+    let c = &a / &b;
+
+    assert!(!c.is_proper());
+    assert!(Gaussian::try_from(&c).is_err());
+    assert!(approx_eq!(f64, c.pi, -0.75));
+    assert!((&c * &b).is_proper());
+}
+
+#[test]
+fn div_0() {
+    let a = GaussianNat::from(Gaussian { mu: 1., sigma: 2. });
+    let c = &a / &a;
+
+    assert!(c.is_uniform());
+    assert_eq!(c.tau, 0.);
+}
+
+#[test]
+fn sum() {
+    let mut rng = rand::thread_rng();
+
+    for _i in 0..10 {
+        let a = Gaussian { mu: rng.gen_range(-1000., 1000.), sigma: rng.gen_range(1., 500.) };
+        let b = Gaussian { mu: rng.gen_range(-1000., 1000.), sigma: rng.gen_range(1., 500.) };
+        let c = Gaussian::try_from(GaussianNat::from(&a) - GaussianNat::from(&b)).unwrap();
+        let d = &a - &b;
+
+        assert!(approx_eq!(f64, c.mu, d.mu, epsilon = 1e-9));
+        assert!(approx_eq!(f64, c.sigma, d.sigma, epsilon = 1e-9));
+    }
+}
+
+#[test]
+fn sum_with_neutral() {
+    let a = GaussianNat::from(Gaussian { mu: 5., sigma: 2. });
+
+    let c = &a + &ZERO;
+    assert!(approx_eq!(f64, c.pi, a.pi));
+    assert!(approx_eq!(f64, c.tau, a.tau));
+
+    assert!((&a + &ONE).is_uniform());
+    assert!((&ZERO - &ZERO).pi.is_infinite());
+    assert_eq!((&ZERO - &ZERO).mu(), 0.);
+}
+
+#[test]
+fn scale() {
+    let a = GaussianNat::from(Gaussian { mu: 5., sigma: 2. });
+    let c = Gaussian::try_from(&a * -3.).unwrap();
+
+    assert!(approx_eq!(f64, c.mu, -15.));
+    assert!(approx_eq!(f64, c.sigma, 6.));
+}
+
+#[test]
+fn scale_by_0() {
+    let a = GaussianNat::from(Gaussian { mu: 5., sigma: 2. });
+
+    for c in [&a * 0., &ONE * 0., &a / f64::INFINITY] {
+        assert!(c.pi.is_infinite());
+        assert_eq!(c.tau, 0.);
+    }
+
+    let mut b = ONE;
+    b *= 0.;
+    assert_eq!(b.mu(), 0.);
+    assert!(b.pi.is_infinite());
+}
+
+#[test]
+fn truncation_of_improper() {
+    let c = GaussianNat { pi: -1., tau: 0. };
+
+    assert!(c.leq_eps(1.).is_none());
+    assert!(c.greater_eps(1.).is_none());
+}
+
+#[test]
+fn shift() {
+    let a = GaussianNat::from(Gaussian { mu: 5., sigma: 2. });
+    let c = Gaussian::try_from(a.shift(-7.)).unwrap();
+
+    assert!(approx_eq!(f64, c.mu, -2.));
+    assert!(approx_eq!(f64, c.sigma, 2.));
+    assert!(ONE.shift(3.).is_uniform());
+}
+
+
+#[test]
+fn zero_point_mass() {
+    let a = GaussianNat::from(Gaussian { mu: 0., sigma: 0. });
+
+    assert!(a.pi.is_infinite());
+    assert_eq!(a.mu(), 0.);
+}
+
+#[test]
+#[should_panic(expected = "Point mass")]
+fn nonzero_point_mass() {
+    let _ = GaussianNat::from(Gaussian { mu: 1., sigma: 0. });
+}
@@ -125,9 +125,244 @@ fn divmul_numerical_stability() {
 fn div_0() {
     let mut rng = rand::thread_rng();
 
-    let a = Gaussian { mu: rng.gen(), sigma: rng.gen() };
+    let a: Gaussian = Gaussian { mu: rng.gen(), sigma: rng.gen() };
     let c = &a / &a;
 
     assert!(c.mu.is_finite());
     assert!(c.sigma.is_finite());
-}
\ No newline at end of file
+}
+
+// statrs erfc is accurate up to about 1e-10, and the variance loses a couple more digits
+fn assert_close(expected: f64, actual: f64, scale: f64) {
+    assert!(approx_eq!(f64, expected, actual, epsilon = 1e-7 * f64::max(expected.abs(), scale), ulps = 4),
+            "expected {}, got {}", expected, actual);
+}
+
+#[test]
+fn erfcx_reference() {
+    // reference values computed with mpmath at 50 digits
+    let reference = [
+        (-2., 108.94090438997797),
+        (0., 1.),
+        (0.5, 0.6156903441929259),
+        (2.9, 0.18460182595559095),
+        (3., 0.17900115118138995),
+        (10., 0.05614099274382259),
+        (30., 0.01879588886141675),
+        (1e5, 5.6418958351954685e-6),
+    ];
+
+    for (x, expected) in &reference {
+        assert_close(*expected, erfcx(*x), 0.);
+    }
+}
+
+#[test]
+fn greater_eps_reference() {
+    // truncated moments computed with mpmath at 300 digits
+    let reference = [
+        ((0., 1., 0.), (0.5641895835477563, 0.602810274989087)),
+        ((100., 50., 0.736), (100.27463093283208, 49.45025000814633)),
+        ((0., 500., 1.472), (283.03256364879918, 301.02887518668196)),
+        ((-200., 300., 1.472), (115.02407962483862, 135.85551609484854)),
+        ((-3000., 100., 1.472), (3.136005271816753, 2.351958862307007)),
+        ((-5000., 70., 1.472), (1.96175987818544, 0.6925572811409577)),
+        ((1500., 200., 1.472), (1500., 200.)),
+        ((-50., 0.5, 1.472), (1.474428275713209, 0.0034339384871589896)),
+    ];
+
+    for ((mu, sigma, eps), (mu_post, sigma_post)) in &reference {
+        let posterior = Gaussian { mu: *mu, sigma: *sigma }.greater_eps_posterior(*eps);
+
+        assert_close(*mu_post, posterior.mu, *eps);
+        assert_close(*sigma_post, posterior.sigma, 0.);
+    }
+}
+
+#[test]
+fn leq_eps_reference() {
+    // truncated moments computed with mpmath at 300 digits
+    let reference = [
+        ((0., 1., 0.736), (0., 0.5584070325666619)),
+        ((3., 2., 0.736), (0.24331078391293546, 0.5304446686849616)),
+        ((-400., 300., 0.736), (-0.0016050180293767618, 0.6009384292515726)),
+        ((3000., 100., 0.736), (0.10695486436327474, 0.5894620600320996)),
+        ((-5000., 80., 0.736), (-0.26002104991705345, 0.5315021046072079)),
+        ((0.1, 0.01, 0.736), (0.1, 0.01)),
+        ((2., 1e-3, 0.736), (0.7359996044306273, 5.594193966134408e-7)),
+        ((-2., 0.5, 0.736), (-0.6486089626934479, 0.11747795814928977)),
+    ];
+
+    for ((mu, sigma, eps), (mu_post, sigma_post)) in &reference {
+        let posterior = Gaussian { mu: *mu, sigma: *sigma }.leq_eps_posterior(*eps);
+
+        assert_close(*mu_post, posterior.mu, *eps);
+        assert_close(*sigma_post, posterior.sigma, 0.);
+    }
+}
+
+#[test]
+fn greater_eps_continuity() {
+    // a heavy favourite losing: the message must change smoothly with the rating gap
+    let mut prev = Gaussian { mu: 0., sigma: 300. }.greater_eps(1.472);
+
+    for i in 1..5000 {
+        let curr = Gaussian { mu: -i as f64, sigma: 300. }.greater_eps(1.472);
+
+        assert!(curr.mu.is_finite() && curr.sigma.is_finite());
+        assert!((curr.mu - prev.mu).abs() < 5., "jump at {}: {:?} {:?}", i, prev, curr);
+        assert!((curr.sigma - prev.sigma).abs() < 5., "jump at {}: {:?} {:?}", i, prev, curr);
+
+        prev = curr;
+    }
+}
+
+
+fn statrs_normal(gaussian: &Gaussian) -> statrs::distribution::Normal {
+    statrs::distribution::Normal::new(gaussian.mu, gaussian.std_dev()).unwrap()
+}
+
+fn random_gaussian(rng: &mut ThreadRng) -> Gaussian {
+    Gaussian { mu: rng.gen_range(-1000., 1000.), sigma: rng.gen_range(1., 500.) }
+}
+
+/// Integrates `f` over `[lo, hi]` with the trapezoidal rule
+fn integrate(f: impl Fn(f64) -> f64, lo: f64, hi: f64) -> f64 {
+    let steps = 100_000;
+    let h = (hi - lo) / steps as f64;
+
+    (1..steps).map(|i| f(lo + i as f64 * h)).sum::<f64>() * h + (f(lo) + f(hi)) * h / 2.
+}
+
+#[test]
+fn pdf_cdf() {
+    use statrs::distribution::{Continuous, Univariate};
+
+    let mut rng = rand::thread_rng();
+
+    for _i in 0..10 {
+        let a = random_gaussian(&mut rng);
+        let reference = statrs_normal(&a);
+
+        for _j in 0..10 {
+            let x = rng.gen_range(a.mu - 3. * a.sigma, a.mu + 3. * a.sigma);
+
+            assert!(approx_eq!(f64, a.pdf(x), reference.pdf(x), epsilon = 1e-12 * reference.pdf(x)));
+            assert!(approx_eq!(f64, a.log_pdf(x), reference.ln_pdf(x), epsilon = 1e-9));
+            assert!(approx_eq!(f64, a.cdf(x), reference.cdf(x), epsilon = 1e-9));
+        }
+    }
+}
+
+#[test]
+fn ppf() {
+    use statrs::distribution::InverseCDF;
+
+    let mut rng = rand::thread_rng();
+
+    for _i in 0..10 {
+        let a = random_gaussian(&mut rng);
+        let reference = statrs_normal(&a);
+
+        for _j in 0..10 {
+            let p = rng.gen_range(1e-6, 1. - 1e-6);
+
+            assert!(approx_eq!(f64, a.ppf(p), reference.inverse_cdf(p), epsilon = 1e-9 * a.sigma));
+            assert!(approx_eq!(f64, a.cdf(a.ppf(p)), p, epsilon = 1e-9));
+        }
+    }
+}
+
+#[test]
+fn kl_divergence() {
+    use statrs::distribution::Continuous;
+
+    let mut rng = rand::thread_rng();
+
+    for _i in 0..10 {
+        let a = random_gaussian(&mut rng);
+        let b = Gaussian { mu: a.mu + rng.gen_range(-100., 100.), sigma: a.sigma * rng.gen_range(0.5, 2.) };
+        let (p, q) = (statrs_normal(&a), statrs_normal(&b));
+        let expected = integrate(|x| p.pdf(x) * (p.ln_pdf(x) - q.ln_pdf(x)),
+                                 a.mu - 10. * a.sigma, a.mu + 10. * a.sigma);
+
+        assert!(approx_eq!(f64, a.kl_divergence(&b), expected, epsilon = 1e-6));
+        assert!(approx_eq!(f64, a.kl_divergence(&a), 0., epsilon = 1e-12));
+    }
+}
+
+#[test]
+fn hellinger_distance() {
+    use statrs::distribution::Continuous;
+
+    let mut rng = rand::thread_rng();
+
+    for _i in 0..10 {
+        let a = random_gaussian(&mut rng);
+        let b = Gaussian { mu: a.mu + rng.gen_range(-100., 100.), sigma: a.sigma * rng.gen_range(0.5, 2.) };
+        let (p, q) = (statrs_normal(&a), statrs_normal(&b));
+        let overlap = integrate(|x| (p.pdf(x) * q.pdf(x)).sqrt(),
+                                a.mu - 20. * a.sigma, a.mu + 20. * a.sigma);
+
+        assert!(approx_eq!(f64, a.hellinger_distance(&b).powi(2), 1. - overlap, epsilon = 1e-6));
+        assert!(approx_eq!(f64, a.hellinger_distance(&b), b.hellinger_distance(&a), epsilon = 1e-12));
+        assert!(approx_eq!(f64, a.hellinger_distance(&a), 0., epsilon = 1e-6));
+    }
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn sampling() {
+    let mut rng = rand::thread_rng();
+    let a = Gaussian { mu: 1500., sigma: 500. };
+    let n = 100_000;
+    let samples: Vec<f64> = (&a).sample_iter(&mut rng).take(n).collect();
+
+    let mean = samples.iter().sum::<f64>() / n as f64;
+    let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+
+    assert!((mean - a.mu).abs() < 5. * a.std_dev() / (n as f64).sqrt());
+    assert!((variance.sqrt() - a.std_dev()).abs() < 0.02 * a.std_dev());
+}
+
+#[test]
+fn f32_arithmetic() {
+    let mut rng = rand::thread_rng();
+
+    for _i in 0..10 {
+        let a = Gaussian { mu: rng.gen_range(-1000., 1000.), sigma: rng.gen_range(1., 500.) };
+        let b = Gaussian { mu: rng.gen_range(-1000., 1000.), sigma: rng.gen_range(1., 500.) };
+        let (a32, b32): (Gaussian<f32>, Gaussian<f32>) = (a.cast().unwrap(), b.cast().unwrap());
+
+        for (c, c32) in [(&a + &b, &a32 + &b32), (&a - &b, &a32 - &b32), (&a * &b, &a32 * &b32),
+                         (&a * 3., &a32 * 3f32), (-&a, -&a32)].iter().cloned() {
+            let c32 = Gaussian::<f64>::from(c32);
+
+            assert!(approx_eq!(f64, c.mu, c32.mu, epsilon = 1e-3));
+            assert!(approx_eq!(f64, c.sigma, c32.sigma, epsilon = 1e-6 * c.sigma));
+        }
+    }
+}
+
+#[test]
+fn f32_assignment() {
+    let mut a = Gaussian { mu: 1500f32, sigma: 500. };
+
+    a += Gaussian { mu: 100., sigma: 0. };
+    a *= &Gaussian::one();
+    a /= 2.;
+
+    assert_eq!(a.mu, 800.);
+    assert_eq!(a.sigma, 250.);
+}
+
+#[test]
+fn cast() {
+    let a = Gaussian { mu: 1234.5678, sigma: 321.123 };
+    let b = Gaussian::<f64>::from(a.cast::<f32>().unwrap());
+
+    assert!(approx_eq!(f64, a.mu, b.mu, epsilon = 1e-4));
+    assert!(approx_eq!(f64, a.sigma, b.sigma, epsilon = 1e-4));
+    assert!(ONE.cast::<f32>().unwrap().sigma.is_infinite());
+    assert!(Gaussian { mu: 1e300, sigma: 1. }.cast::<f32>().is_none());
+}
@@ -0,0 +1,496 @@
+use crate::{
+    cached, read_cache, write_cache, CFContest, CFRatingChange, CFResponse, CFStandings, CFUser,
+    CachedContest, Contest, ContestSummary, ContestUpdate, Error, ValidationPolicy,
+};
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Upper bound of a single backoff pause
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Rating changes of a contest are expected to be published during this period after its start
+const PENDING_PERIOD: Duration = Duration::from_secs(14 * 24 * 60 * 60);
+/// Handles per `user.info` request, keeps the URL reasonably short
+const HANDLES_PER_REQUEST: usize = 300;
+
+/// Settings of a `Client`
+pub struct ClientBuilder {
+    base_url: String,
+    timeout: Duration,
+    user_agent: String,
+    delay: Duration,
+    requests_per_second: f64,
+    max_attempts: usize,
+    backoff: Duration,
+    validation: ValidationPolicy,
+    offline: bool,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        ClientBuilder {
+            base_url: "https://codeforces.com/api".to_string(),
+            timeout: Duration::from_secs(30),
+            user_agent: concat!("read_codeforces/", env!("CARGO_PKG_VERSION")).to_string(),
+            delay: Duration::from_millis(0),
+            // the documented limit is one call per two seconds
+            requests_per_second: 0.5,
+            max_attempts: 5,
+            backoff: Duration::from_secs(1),
+            validation: ValidationPolicy::default(),
+            offline: false,
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// URL the method names are appended to, e.g. a local stub in tests
+    pub fn base_url<S: Into<String>>(mut self, base_url: S) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn user_agent<S: Into<String>>(mut self, user_agent: S) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Pause before every request, on top of the request budget
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Requests are spaced so that there are at most this many per second, including retries
+    pub fn requests_per_second(mut self, requests_per_second: f64) -> Self {
+        assert!(requests_per_second > 0., "Request budget must be positive");
+        self.requests_per_second = requests_per_second;
+        self
+    }
+
+    /// Total number of tries of a request failing with a rate-limit or transient error.
+    /// Then `Error::RetriesExhausted` is returned, unless there is a single attempt,
+    /// whose error is returned as is.
+    pub fn max_attempts(mut self, max_attempts: usize) -> Self {
+        assert!(max_attempts > 0, "At least one attempt is needed");
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Pause after the first failed attempt, doubled after every next one
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// How to treat anomalies in the rating changes, applied on every load of a contest, cached or not
+    pub fn validation(mut self, validation: ValidationPolicy) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Fail on cache misses instead of requesting the API
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn build(self) -> Result<Client, Error> {
+        let http = reqwest::blocking::Client::builder()
+            .timeout(self.timeout)
+            .user_agent(self.user_agent)
+            .build()
+            .map_err(|err| Error::Http(err.to_string()))?;
+
+        Ok(Client {
+            base_url: self.base_url.trim_end_matches('/').to_string(),
+            delay: self.delay,
+            interval: Duration::from_secs_f64(1. / self.requests_per_second),
+            max_attempts: self.max_attempts,
+            backoff: self.backoff,
+            validation: self.validation,
+            last_request: Mutex::new(None),
+            offline: self.offline,
+            http,
+        })
+    }
+}
+
+/// Codeforces API client with a file cache
+pub struct Client {
+    base_url: String,
+    delay: Duration,
+    /// Minimal time between the starts of two requests
+    interval: Duration,
+    max_attempts: usize,
+    backoff: Duration,
+    validation: ValidationPolicy,
+    last_request: Mutex<Option<Instant>>,
+    offline: bool,
+    http: reqwest::blocking::Client,
+}
+
+impl Client {
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    /// Client with the default settings
+    pub fn new() -> Result<Client, Error> {
+        Client::builder().build()
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Waits until the request budget allows one more request
+    fn throttle(&self) {
+        thread::sleep(self.delay);
+        let mut last_request = self.last_request.lock().unwrap();
+        if let Some(last) = *last_request {
+            let next = last + self.interval;
+            let now = Instant::now();
+            if next > now {
+                thread::sleep(next - now);
+            }
+        }
+        *last_request = Some(Instant::now());
+    }
+
+    /// Pause before the attempt following `attempt` failed ones: exponential with equal jitter
+    fn backoff(&self, attempt: usize) -> Duration {
+        let exp = self
+            .backoff
+            .checked_mul(1 << (attempt - 1).min(16))
+            .map_or(MAX_BACKOFF, |backoff| backoff.min(MAX_BACKOFF));
+        exp / 2 + exp.mul_f64(rand::thread_rng().gen_range(0., 0.5))
+    }
+
+    /// Request a method of the Codeforces API, retrying on rate-limit and transient errors
+    fn request<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, Error> {
+        let mut attempt = 1;
+        loop {
+            self.throttle();
+            match self.attempt(method, query) {
+                Ok(result) => return Ok(result),
+                Err((err, false)) => return Err(err),
+                Err((err, true)) if self.max_attempts == 1 => return Err(err),
+                Err((err, true)) if attempt == self.max_attempts => {
+                    return Err(Error::RetriesExhausted {
+                        attempts: attempt,
+                        last: Box::new(err),
+                    })
+                }
+                Err((_, true)) => {
+                    thread::sleep(self.backoff(attempt));
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// The error comes with whether another attempt may succeed
+    fn attempt<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, (Error, bool)> {
+        let response = self
+            .http
+            .get(&format!("{}/{}", self.base_url, method))
+            .query(query)
+            .send()
+            .map_err(|err| (Error::Http(err.to_string()), true))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .map_err(|err| (Error::Http(err.to_string()), true))?;
+
+        match serde_json::from_str(&body) {
+            Ok(CFResponse::OK { result }) => Ok(result),
+            Ok(CFResponse::FAILED { comment }) => {
+                let rate_limited = comment.contains("Call limit exceeded");
+                Err((Error::Api(comment), rate_limited))
+            }
+            Err(_) if status.is_server_error() || status.as_u16() == 429 => {
+                Err((Error::Status(status.as_u16()), true))
+            }
+            Err(err) => Err((
+                Error::Http(format!(
+                    "Failed to parse Codeforces API response as JSON: {}",
+                    err
+                )),
+                false,
+            )),
+        }
+    }
+
+    /// Retrieve a contest with a particular ID. If there's a cached entry with the same name in the
+    /// json/ directly, that will be used. This way, you can process your own custom contests.
+    /// If there is no cached entry, this function will attempt to retrieve one from Codeforces,
+    /// unless the client is offline. The raw rating changes are cached,
+    /// so a change of the validation policy also applies to contests fetched before.
+    pub fn contest<P: AsRef<Path>>(
+        &self,
+        cache_dir: P,
+        contest_id: usize,
+    ) -> Result<Contest, Error> {
+        self.load_contest(cache_dir.as_ref(), contest_id, false)
+    }
+
+    /// Same as `contest`, see `Contest::validate` for `party_ranks`
+    fn load_contest(
+        &self,
+        cache_dir: &Path,
+        contest_id: usize,
+        party_ranks: bool,
+    ) -> Result<Contest, Error> {
+        let cache_file = cache_dir.join(format!("{}.json", contest_id));
+
+        let cached: CachedContest = cached(&cache_file, self.offline, || {
+            let changes: Vec<CFRatingChange> = self.request(
+                "contest.ratingChanges",
+                &[("contestId", contest_id.to_string())],
+            )?;
+            if changes.is_empty() {
+                return Err(Error::InvalidData("No rating changes yet".to_string()));
+            }
+            Ok(CachedContest::Changes(changes))
+        })?;
+        cached.validate(&self.validation, party_ranks)
+    }
+
+    /// Same as `contest`, but also fills `Contest::teams` from the official standings.
+    /// The standings are cached in the same directory as `<id>_standings.json`.
+    /// Ranks count parties rather than their members, as Codeforces ranks them in team contests.
+    pub fn contest_with_teams<P: AsRef<Path>>(
+        &self,
+        cache_dir: P,
+        contest_id: usize,
+    ) -> Result<Contest, Error> {
+        let mut contest = self.load_contest(cache_dir.as_ref(), contest_id, true)?;
+
+        let cache_file = cache_dir
+            .as_ref()
+            .join(format!("{}_standings.json", contest_id));
+        let standings: CFStandings = cached(&cache_file, self.offline, || {
+            self.request(
+                "contest.standings",
+                &[
+                    ("contestId", contest_id.to_string()),
+                    ("showUnofficial", "false".to_string()),
+                ],
+            )
+        })?;
+
+        contest.teams = standings
+            .rows
+            .into_iter()
+            .filter(|row| row.party.participantType == "CONTESTANT" && row.party.members.len() > 1)
+            .map(|row| {
+                row.party
+                    .members
+                    .into_iter()
+                    .map(|member| member.handle)
+                    .collect()
+            })
+            .collect();
+
+        Ok(contest)
+    }
+
+    /// All finished non-gym contests in chronological order
+    pub fn finished_contests(&self) -> Result<Vec<ContestSummary>, Error> {
+        let contests: Vec<CFContest> =
+            self.request("contest.list", &[("gym", "false".to_string())])?;
+
+        let mut finished: Vec<_> = contests
+            .into_iter()
+            .filter(|contest| contest.phase == "FINISHED")
+            .filter_map(|contest| {
+                Some(ContestSummary {
+                    id: contest.id,
+                    name: contest.name,
+                    start_time_seconds: contest.startTimeSeconds?,
+                })
+            })
+            .collect();
+        finished.sort_by_key(|contest| (contest.start_time_seconds, contest.id));
+
+        Ok(finished)
+    }
+
+    /// Merges into `known_ids` the finished rated contests missing from them and caches their rating
+    /// changes. The result is ordered by the rating update time, assuming `known_ids` are in order;
+    /// a known contest missing from the cache is placed by its start time instead.
+    /// A contest without rating changes is skipped, so it is
+    /// rechecked on the next update, unless it started more than `PENDING_PERIOD` ago:
+    /// then it is recorded as unrated in `unrated.json` of `cache_dir` and never requested again.
+    /// A contest that fails to be fetched is reported and skipped, so it is retried next time.
+    pub fn update_contest_ids<P: AsRef<Path>>(
+        &self,
+        cache_dir: P,
+        known_ids: &[usize],
+    ) -> Result<ContestUpdate, Error> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as usize);
+        let unrated_file = cache_dir.as_ref().join("unrated.json");
+        let mut unrated: BTreeSet<usize> = read_cache(&unrated_file)?.unwrap_or_default();
+        let known: HashSet<usize> = known_ids.iter().cloned().collect();
+
+        let finished = self.finished_contests()?;
+        let start_times: HashMap<usize, usize> = finished
+            .iter()
+            .map(|contest| (contest.id, contest.start_time_seconds))
+            .collect();
+
+        let mut new = Vec::new();
+        let mut failed = Vec::new();
+        for contest in finished {
+            if known.contains(&contest.id) || unrated.contains(&contest.id) {
+                continue;
+            }
+
+            match self.rated_contest(cache_dir.as_ref(), contest.id) {
+                Ok(Some(time)) => new.push((time, contest.id)),
+                Ok(None) => {
+                    if contest.start_time_seconds + PENDING_PERIOD.as_secs() as usize <= now {
+                        unrated.insert(contest.id);
+                    }
+                }
+                Err(err) => failed.push((contest.id, err)),
+            }
+        }
+        write_cache(&unrated_file, &unrated)?;
+
+        // insert the latest first, moving back past the known contests rated later
+        new.sort();
+        let mut ids = known_ids.to_vec();
+        let mut times = HashMap::new();
+        let mut pos = ids.len();
+        for &(time, id) in new.iter().rev() {
+            while pos > 0 {
+                let prev = ids[pos - 1];
+                let prev_time = match times.get(&prev) {
+                    Some(&prev_time) => prev_time,
+                    None => {
+                        let prev_time = self.known_time(cache_dir.as_ref(), prev, &start_times)?;
+                        times.insert(prev, prev_time);
+                        prev_time
+                    }
+                };
+                if prev_time.is_none_or(|prev_time| prev_time <= time) {
+                    break;
+                }
+                pos -= 1;
+            }
+            ids.insert(pos, id);
+        }
+
+        Ok(ContestUpdate {
+            ids,
+            new: new.into_iter().map(|(_, id)| id).collect(),
+            failed,
+        })
+    }
+
+    /// Rating update time of a cached contest, otherwise its start time if it is finished
+    fn known_time(
+        &self,
+        cache_dir: &Path,
+        contest_id: usize,
+        start_times: &HashMap<usize, usize>,
+    ) -> Result<Option<usize>, Error> {
+        let cached: Option<CachedContest> =
+            read_cache(&cache_dir.join(format!("{}.json", contest_id)))?;
+
+        Ok(cached
+            .and_then(|cached| cached.time_seconds())
+            .or_else(|| start_times.get(&contest_id).cloned()))
+    }
+
+    /// Caches the rating changes of a contest unless it has none yet, returns the rating update time.
+    /// Fails if they are invalid under the validation policy, ranks of team contests are accepted.
+    fn rated_contest(&self, cache_dir: &Path, contest_id: usize) -> Result<Option<usize>, Error> {
+        let cache_file = cache_dir.join(format!("{}.json", contest_id));
+        let cached = match read_cache(&cache_file)? {
+            Some(cached) => cached,
+            None => {
+                let changes: Vec<CFRatingChange> = self.request(
+                    "contest.ratingChanges",
+                    &[("contestId", contest_id.to_string())],
+                )?;
+                if changes.is_empty() {
+                    return Ok(None);
+                }
+                let cached = CachedContest::Changes(changes);
+                write_cache(&cache_file, &cached)?;
+                cached
+            }
+        };
+        let contest = cached.validate(&self.validation, true)?;
+
+        Ok(Some(contest.time_seconds))
+    }
+
+    /// Maps the given handles to the current handles of their owners, when they differ.
+    /// Handles of deleted accounts are skipped.
+    pub fn current_handles(&self, handles: &[String]) -> Result<HashMap<String, String>, Error> {
+        let mut ans = HashMap::new();
+
+        for chunk in handles.chunks(HANDLES_PER_REQUEST) {
+            let mut chunk = chunk.to_vec();
+
+            while !chunk.is_empty() {
+                let users: Result<Vec<CFUser>, Error> =
+                    self.request("user.info", &[("handles", chunk.join(";"))]);
+
+                match users {
+                    Ok(users) => {
+                        if users.len() != chunk.len() {
+                            return Err(Error::InvalidData(format!(
+                                "user.info returned {} users for {} handles",
+                                users.len(),
+                                chunk.len()
+                            )));
+                        }
+                        for (old, user) in chunk.drain(..).zip(users) {
+                            if old != user.handle {
+                                ans.insert(old, user.handle);
+                            }
+                        }
+                    }
+                    Err(Error::Api(comment)) => {
+                        let missing = comment
+                            .strip_prefix("handles: User with handle ")
+                            .and_then(|rest| rest.strip_suffix(" not found"))
+                            .ok_or_else(|| Error::Api(comment.clone()))?;
+                        let len = chunk.len();
+                        chunk.retain(|handle| !handle.eq_ignore_ascii_case(missing));
+                        if chunk.len() == len {
+                            return Err(Error::Api(comment));
+                        }
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+
+        Ok(ans)
+    }
+}
@@ -1,11 +1,63 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::path::Path;
-use std::{thread, time};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+mod client;
+
+pub use client::{Client, ClientBuilder};
+
+/// Failure to obtain a contest
+#[derive(Debug)]
+pub enum Error {
+    /// There is no cached entry and fetching is not allowed
+    MissingCache(PathBuf),
+    CorruptCache {
+        path: PathBuf,
+        message: String,
+    },
+    Io(String),
+    /// The request failed or the response is not a valid API response
+    Http(String),
+    /// The server responded with this error status instead of an API response
+    Status(u16),
+    /// The API responded with `FAILED` and this comment
+    Api(String),
+    /// The data is inconsistent
+    InvalidData(String),
+    /// A rate-limit or transient error persisted through all the attempts
+    RetriesExhausted {
+        attempts: usize,
+        last: Box<Error>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingCache(path) => write!(f, "No cached entry {}", path.display()),
+            Error::CorruptCache { path, message } => {
+                write!(f, "Corrupt cache {}: {}", path.display(), message)
+            }
+            Error::Io(message) => write!(f, "{}", message),
+            Error::Http(message) => write!(f, "HTTP error: {}", message),
+            Error::Status(status) => write!(f, "HTTP status {}", status),
+            Error::Api(comment) => write!(f, "Codeforces API error: {}", comment),
+            Error::InvalidData(message) => write!(f, "Invalid data: {}", message),
+            Error::RetriesExhausted { attempts, last } => {
+                write!(f, "Gave up after {} attempts: {}", attempts, last)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
 
 /// General response from the Codeforces API
-#[allow(non_snake_case)]
+#[allow(non_snake_case, clippy::upper_case_acronyms)]
 #[derive(Serialize, Deserialize)]
 #[serde(tag = "status")]
 enum CFResponse<T> {
@@ -26,6 +78,67 @@ struct CFRatingChange {
     newRating: i32,
 }
 
+/// API documentation: https://codeforces.com/apiHelp/objects#Member
+#[derive(Serialize, Deserialize)]
+struct CFMember {
+    handle: String,
+}
+
+/// API documentation: https://codeforces.com/apiHelp/objects#Party
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize)]
+struct CFParty {
+    members: Vec<CFMember>,
+    participantType: String,
+}
+
+/// API documentation: https://codeforces.com/apiHelp/objects#RanklistRow
+#[derive(Serialize, Deserialize)]
+struct CFRanklistRow {
+    party: CFParty,
+}
+
+/// API documentation: https://codeforces.com/apiHelp/methods#contest.standings
+#[derive(Serialize, Deserialize)]
+struct CFStandings {
+    rows: Vec<CFRanklistRow>,
+}
+
+/// API documentation: https://codeforces.com/apiHelp/objects#Contest
+#[allow(non_snake_case)]
+#[derive(Serialize, Deserialize)]
+struct CFContest {
+    id: usize,
+    name: String,
+    phase: String,
+    startTimeSeconds: Option<usize>,
+}
+
+/// API documentation: https://codeforces.com/apiHelp/objects#User
+#[derive(Serialize, Deserialize)]
+struct CFUser {
+    handle: String,
+}
+
+/// A finished contest from `contest.list`, rated or not
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContestSummary {
+    pub id: usize,
+    pub name: String,
+    pub start_time_seconds: usize,
+}
+
+/// Result of `Client::update_contest_ids`
+#[derive(Debug)]
+pub struct ContestUpdate {
+    /// The known ids with the new rated contests merged in by their rating update time
+    pub ids: Vec<usize>,
+    /// The new rated contests in chronological order
+    pub new: Vec<usize>,
+    /// Contests that failed to be fetched, retried on the next update
+    pub failed: Vec<(usize, Error)>,
+}
+
 /// Represents the outcome of a contest
 #[derive(Serialize, Deserialize)]
 pub struct Contest {
@@ -33,124 +146,543 @@ pub struct Contest {
     pub name: String,
     pub time_seconds: usize,
     pub standings: Vec<(String, usize, usize)>,
+    /// Codeforces rating of every participant before the contest, empty in old caches
+    #[serde(default)]
+    pub old_ratings: HashMap<String, i32>,
+    /// Handles of the members of every team with more than one member, see `get_contest_with_teams`
+    #[serde(default)]
+    pub teams: Vec<Vec<String>>,
+    /// Data-quality report: anomalies fixed with `Action::Warn` while reading the contest
+    #[serde(default)]
+    pub issues: Vec<Issue>,
 }
 
-/// Check the integrity of our API response and convert it into a more convenient format
-impl TryFrom<Vec<CFRatingChange>> for Contest {
-    type Error = String;
+/// Contents of a cache file: the raw rating changes, validated on every load,
+/// or a contest already validated by older versions, used as it is
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum CachedContest {
+    Changes(Vec<CFRatingChange>),
+    Contest(Contest),
+}
 
-    fn try_from(json_contest: Vec<CFRatingChange>) -> Result<Self, Self::Error> {
-        let first_change = json_contest.get(0).ok_or("Empty standings")?;
+impl CachedContest {
+    /// See `Contest::validate`
+    fn validate(self, policy: &ValidationPolicy, party_ranks: bool) -> Result<Contest, Error> {
+        match self {
+            CachedContest::Changes(changes) => {
+                Contest::validate(&changes, policy, party_ranks).map_err(Error::InvalidData)
+            }
+            CachedContest::Contest(contest) => Ok(contest),
+        }
+    }
+
+    /// Rating update time of the first row, `None` if there are no rows
+    fn time_seconds(&self) -> Option<usize> {
+        match self {
+            CachedContest::Changes(changes) => {
+                changes.first().map(|change| change.ratingUpdateTimeSeconds)
+            }
+            CachedContest::Contest(contest) => Some(contest.time_seconds),
+        }
+    }
+}
+
+/// Kind of inconsistency in the rating changes of a contest
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Anomaly {
+    /// Rows of other contests, fixed by dropping them
+    InconsistentId,
+    /// Rows named unlike the first row, fixed by dropping them
+    InconsistentName,
+    /// Fixed by keeping the time of the first row
+    InconsistentTime,
+    /// Fixed by appending `_clone` to all but the last occurrence
+    DuplicateUser,
+    /// Tied rows whose rank is not their first position, fixed by tying them at that position
+    InvalidRank,
+}
+
+/// What to do on an anomaly
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    /// Reject the contest
+    Error,
+    /// Fix it and report it in `Contest::issues`
+    Warn,
+    /// Fix it silently
+    Fix,
+}
+
+/// Action for every kind of anomaly, see `Anomaly` for the fixes
+#[derive(Clone, Debug)]
+pub struct ValidationPolicy {
+    pub inconsistent_ids: Action,
+    pub inconsistent_names: Action,
+    pub inconsistent_times: Action,
+    pub duplicate_users: Action,
+    pub invalid_ranks: Action,
+}
+
+impl Default for ValidationPolicy {
+    /// Tolerates the known Codeforces glitches: contests 61,318,347,373,381,400,404,405
+    /// each contain one time discrepancy, usually 4 hours late,
+    /// and contests 447,472,615 have duplicate users.
+    /// Note that duplicate users are accepted in every contest, not only in those three;
+    /// set `Anomaly::DuplicateUser` to `Action::Error` to reject them elsewhere.
+    fn default() -> Self {
+        ValidationPolicy {
+            inconsistent_ids: Action::Error,
+            inconsistent_names: Action::Error,
+            inconsistent_times: Action::Warn,
+            duplicate_users: Action::Warn,
+            invalid_ranks: Action::Error,
+        }
+    }
+}
+
+impl ValidationPolicy {
+    /// Rejects every anomaly
+    pub fn strict() -> Self {
+        ValidationPolicy {
+            inconsistent_ids: Action::Error,
+            inconsistent_names: Action::Error,
+            inconsistent_times: Action::Error,
+            duplicate_users: Action::Error,
+            invalid_ranks: Action::Error,
+        }
+    }
+
+    pub fn action(&self, anomaly: Anomaly) -> Action {
+        match anomaly {
+            Anomaly::InconsistentId => self.inconsistent_ids,
+            Anomaly::InconsistentName => self.inconsistent_names,
+            Anomaly::InconsistentTime => self.inconsistent_times,
+            Anomaly::DuplicateUser => self.duplicate_users,
+            Anomaly::InvalidRank => self.invalid_ranks,
+        }
+    }
+
+    pub fn set(&mut self, anomaly: Anomaly, action: Action) -> &mut Self {
+        *match anomaly {
+            Anomaly::InconsistentId => &mut self.inconsistent_ids,
+            Anomaly::InconsistentName => &mut self.inconsistent_names,
+            Anomaly::InconsistentTime => &mut self.inconsistent_times,
+            Anomaly::DuplicateUser => &mut self.duplicate_users,
+            Anomaly::InvalidRank => &mut self.invalid_ranks,
+        } = action;
+        self
+    }
+}
+
+/// An anomaly fixed with `Action::Warn`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Issue {
+    pub anomaly: Anomaly,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.anomaly, self.message)
+    }
+}
+
+/// Collects the issues of a contest according to a policy
+struct Validator<'a> {
+    policy: &'a ValidationPolicy,
+    issues: Vec<Issue>,
+}
+
+impl Validator<'_> {
+    /// `Ok` if the anomaly is to be fixed
+    fn report(&mut self, anomaly: Anomaly, message: String) -> Result<(), String> {
+        match self.policy.action(anomaly) {
+            Action::Error => return Err(message),
+            Action::Warn => self.issues.push(Issue { anomaly, message }),
+            Action::Fix => {}
+        }
+        Ok(())
+    }
+}
+
+impl Contest {
+    /// Check the integrity of our API response and convert it into a more convenient format.
+    /// With `party_ranks` a rank counts the parties above rather than the rows,
+    /// as in team contests where every member of a party has a row of its own.
+    fn validate(
+        json_contest: &[CFRatingChange],
+        policy: &ValidationPolicy,
+        party_ranks: bool,
+    ) -> Result<Self, String> {
+        let first_change = json_contest.first().ok_or("Empty standings")?;
         let id = first_change.contestId;
-        let name = first_change.contestName.clone();
+        let name = &first_change.contestName;
         let time_seconds = first_change.ratingUpdateTimeSeconds;
 
-        let mut lo_rank = json_contest.len() + 1;
-        let mut hi_rank = json_contest.len() + 1;
-        let mut seen_handles = HashMap::with_capacity(json_contest.len());
-        let mut standings = Vec::with_capacity(json_contest.len());
-
-        for (i, mut change) in json_contest.into_iter().enumerate().rev() {
+        let mut validator = Validator {
+            policy,
+            issues: Vec::new(),
+        };
+        let mut rows = Vec::with_capacity(json_contest.len());
+        for change in json_contest {
             if id != change.contestId {
-                return Err(format!(
-                    "Inconsistent contests ids {} and {}",
-                    id, change.contestId
-                ));
-            }
-            if name != change.contestName {
-                return Err(format!(
-                    "Inconsistent contest names {} and {}",
-                    name, change.contestName
-                ));
+                validator.report(
+                    Anomaly::InconsistentId,
+                    format!("Inconsistent contests ids {} and {}", id, change.contestId),
+                )?;
+            } else if *name != change.contestName {
+                validator.report(
+                    Anomaly::InconsistentName,
+                    format!(
+                        "Inconsistent contest names {} and {}",
+                        name, change.contestName
+                    ),
+                )?;
+            } else {
+                rows.push(change);
             }
+        }
+
+        let mut seen_handles = HashMap::with_capacity(rows.len());
+        let mut handles = Vec::with_capacity(rows.len());
+        let mut old_ratings = HashMap::with_capacity(rows.len());
+
+        for (i, change) in rows.iter().enumerate().rev() {
             if time_seconds != change.ratingUpdateTimeSeconds {
-                // I don't know why but contests 61,318,347,373,381,400,404,405
-                // each contain one discrepancy, usually 4 hours late
-                println!(
-                    "WARNING @ {}: Inconsistent contest times {} and {}",
-                    id, time_seconds, change.ratingUpdateTimeSeconds
-                );
+                validator.report(
+                    Anomaly::InconsistentTime,
+                    format!(
+                        "Inconsistent contest times {} and {}",
+                        time_seconds, change.ratingUpdateTimeSeconds
+                    ),
+                )?;
             }
-            while let Some(j) = seen_handles.insert(change.handle.clone(), i) {
-                // I don't know why but contests 447,472,615 have duplicate users
-                if !(id == 447 || id == 472 || id == 615) {
-                    return Err(format!(
-                        "Duplicate user {} at positions {} and {}",
-                        change.handle, i, j
-                    ));
-                }
-                println!(
-                    "WARNING @ {}: duplicate user {} at positions {} and {}",
-                    id, change.handle, i, j
-                );
-                change.handle += "_clone";
+            let mut handle = change.handle.clone();
+            while let Some(j) = seen_handles.insert(handle.clone(), i) {
+                validator.report(
+                    Anomaly::DuplicateUser,
+                    format!("Duplicate user {} at positions {} and {}", handle, i, j),
+                )?;
+                handle += "_clone";
             }
 
-            if lo_rank == change.rank {
-                if !(lo_rank < i + 2 && i < hi_rank) {
-                    return Err(format!(
-                        "Position {} is not between ranks {} and {}",
-                        i + 1,
-                        lo_rank,
-                        hi_rank
-                    ));
-                }
+            old_ratings.insert(handle.clone(), change.oldRating);
+            handles.push(handle);
+        }
+        handles.reverse();
+
+        // rows with equal consecutive ranks are tied
+        let mut standings = Vec::with_capacity(handles.len());
+        let mut lo = 0;
+        let mut prev_rank = 0;
+        while lo < handles.len() {
+            let rank = rows[lo].rank;
+            let mut hi = lo;
+            while hi + 1 < handles.len() && rows[hi + 1].rank == rank {
+                hi += 1;
+            }
+            // there are at least as many rows above as parties
+            let valid = if party_ranks {
+                prev_rank < rank && rank <= lo + 1
             } else {
-                if !(change.rank < lo_rank && lo_rank == i + 2) {
-                    return Err(format!("Invalid start of rank {}", lo_rank));
-                }
-                hi_rank = lo_rank;
-                lo_rank = change.rank;
+                rank == lo + 1
+            };
+            prev_rank = rank;
+            if !valid {
+                validator.report(
+                    Anomaly::InvalidRank,
+                    format!("Positions {} to {} have rank {}", lo + 1, hi + 1, rank),
+                )?;
             }
-
-            standings.push((change.handle, lo_rank - 1, hi_rank - 2));
+            for handle in &handles[lo..=hi] {
+                standings.push((handle.clone(), lo, hi));
+            }
+            lo = hi + 1;
         }
-        standings.reverse();
 
         Ok(Self {
             id,
-            name,
+            name: name.clone(),
             time_seconds,
             standings,
+            old_ratings,
+            teams: Vec::new(),
+            issues: validator.issues,
         })
     }
 }
 
+/// Same as `Contest::validate` with the default policy
+impl TryFrom<Vec<CFRatingChange>> for Contest {
+    type Error = String;
+
+    fn try_from(json_contest: Vec<CFRatingChange>) -> Result<Self, Self::Error> {
+        Contest::validate(&json_contest, &ValidationPolicy::default(), false)
+    }
+}
+
+const IDS_FILE: &str = "data/contest_ids.json";
+
 /// Get a list of all the contest IDs in chronological order
 pub fn get_contest_ids() -> Vec<usize> {
-    let ids_file = Path::new("data/contest_ids.json");
-    let contests_json = std::fs::read_to_string(&ids_file).expect("Failed to read contest IDs");
+    let contests_json = std::fs::read_to_string(IDS_FILE).expect("Failed to read contest IDs");
     serde_json::from_str(&contests_json).expect("Failed to parse contest IDs as JSON")
 }
 
-/// Retrieve a contest with a particular ID. If there's a cached entry with the same name in the
-/// json/ directly, that will be used. This way, you can process your own custom contests.
-/// If there is no cached entry, this function will attempt to retrieve one from Codeforces.
+/// Replace the list read by `get_contest_ids`
+pub fn write_contest_ids(ids: &[usize]) -> Result<(), Error> {
+    let contests_json = serde_json::to_string(ids).expect("Serialization error");
+    std::fs::write(IDS_FILE, contests_json.replace(',', ", "))
+        .map_err(|err| Error::Io(format!("Failed to write {}: {}", IDS_FILE, err)))
+}
+
+/// Read a contest stored in the same format as the cache, validated with the default policy
+pub fn read_contest<P: AsRef<Path>>(path: P) -> Result<Contest, Error> {
+    let cached: CachedContest = read_cache(path.as_ref())?
+        .ok_or_else(|| Error::MissingCache(path.as_ref().to_path_buf()))?;
+    cached.validate(&ValidationPolicy::default(), false)
+}
+
+/// `Ok(None)` if there is no such file
+fn read_cache<T: DeserializeOwned>(path: &Path) -> Result<Option<T>, Error> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(Error::Io(format!(
+                "Failed to read {}: {}",
+                path.display(),
+                err
+            )))
+        }
+    };
+
+    serde_json::from_str(&json)
+        .map(Some)
+        .map_err(|err| Error::CorruptCache {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })
+}
+
+/// Read `cache_file`, or `fetch` and store it there unless `offline`
+fn cached<T, F>(cache_file: &Path, offline: bool, fetch: F) -> Result<T, Error>
+where
+    T: Serialize + DeserializeOwned,
+    F: FnOnce() -> Result<T, Error>,
+{
+    if let Some(cached) = read_cache(cache_file)? {
+        return Ok(cached);
+    }
+    if offline {
+        return Err(Error::MissingCache(cache_file.to_path_buf()));
+    }
+
+    let result = fetch()?;
+    write_cache(cache_file, &result)?;
+
+    Ok(result)
+}
+
+fn write_cache<T: Serialize>(cache_file: &Path, value: &T) -> Result<(), Error> {
+    let cached_json = serde_json::to_string_pretty(value).expect("Serialization error");
+    std::fs::write(cache_file, cached_json)
+        .map_err(|err| Error::Io(format!("Failed to write {}: {}", cache_file.display(), err)))
+}
+
+/// Process-wide client with the default settings and the given `offline` mode,
+/// shared by the functions below so that together they keep to the request budget
+fn default_client(offline: bool) -> Result<&'static Client, Error> {
+    static ONLINE: OnceLock<Client> = OnceLock::new();
+    static OFFLINE: OnceLock<Client> = OnceLock::new();
+
+    let cell = if offline { &OFFLINE } else { &ONLINE };
+    if let Some(client) = cell.get() {
+        return Ok(client);
+    }
+    let client = Client::builder().offline(offline).build()?;
+    Ok(cell.get_or_init(|| client))
+}
+
+/// Same as `Client::contest` of the default client with the given `offline` mode
+pub fn fetch_contest<P: AsRef<Path>>(
+    cache_dir: P,
+    contest_id: usize,
+    offline: bool,
+) -> Result<Contest, Error> {
+    default_client(offline)?.contest(cache_dir, contest_id)
+}
+
+/// Same as `Client::contest_with_teams` of the default client with the given `offline` mode
+pub fn fetch_contest_with_teams<P: AsRef<Path>>(
+    cache_dir: P,
+    contest_id: usize,
+    offline: bool,
+) -> Result<Contest, Error> {
+    default_client(offline)?.contest_with_teams(cache_dir, contest_id)
+}
+
+/// Same as `fetch_contest` online, but panics on errors
 pub fn get_contest<P: AsRef<Path>>(cache_dir: P, contest_id: usize) -> Contest {
-    let cache_file = cache_dir.as_ref().join(format!("{}.json", contest_id));
-
-    match std::fs::read_to_string(&cache_file) {
-        Ok(cached_json) => serde_json::from_str(&cached_json).expect("Failed to read cache"),
-        Err(_) => {
-            // sleep before requesting to deal with "Call limit exceeded"
-            thread::sleep(time::Duration::from_millis(100));
-            // The contest doesn't appear in our cache, so request it from the Codeforces API.
-            let url = format!(
-                "https://codeforces.com/api/contest.ratingChanges?contestId={}",
-                contest_id
-            );
-            let response = reqwest::blocking::get(&url).expect("HTTP error");
-            let packet: CFResponse<Vec<CFRatingChange>> = response
-                .json()
-                .expect("Failed to parse Codeforces API response as JSON");
-            let contest = match packet {
-                CFResponse::OK { result } => TryFrom::try_from(result).unwrap(),
-                CFResponse::FAILED { comment } => panic!(comment),
-            };
+    fetch_contest(cache_dir, contest_id, false).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Same as `fetch_contest_with_teams` online, but panics on errors
+pub fn get_contest_with_teams<P: AsRef<Path>>(cache_dir: P, contest_id: usize) -> Contest {
+    fetch_contest_with_teams(cache_dir, contest_id, false).unwrap_or_else(|err| panic!("{}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("read_codeforces_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn shared_default_client() {
+        let a = default_client(false).unwrap();
+        let b = default_client(false).unwrap();
+
+        assert!(std::ptr::eq(a, b));
+        assert!(!a.is_offline());
+        assert!(default_client(true).unwrap().is_offline());
+    }
+
+    #[test]
+    fn offline() {
+        let dir = temp_dir("offline");
+        std::fs::write(
+            dir.join("1.json"),
+            r#"{"id": 1, "name": "Round", "time_seconds": 5, "standings": []}"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("2.json"), "{").unwrap();
 
-            let cached_json = serde_json::to_string_pretty(&contest).expect("Serialization error");
-            std::fs::write(&cache_file, cached_json).expect("Failed to write to cache");
-            contest
+        let cached = fetch_contest(&dir, 1, true);
+        let corrupt = fetch_contest(&dir, 2, true);
+        let missing = fetch_contest(&dir, 3, true);
+        let no_standings = fetch_contest_with_teams(&dir, 1, true);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(cached.unwrap().name, "Round");
+        assert!(matches!(corrupt, Err(Error::CorruptCache { .. })));
+        assert!(matches!(missing, Err(Error::MissingCache(path)) if path.ends_with("3.json")));
+        assert!(
+            matches!(no_standings, Err(Error::MissingCache(path)) if path.ends_with("1_standings.json"))
+        );
+    }
+
+    fn change(handle: &str, rank: usize, time: usize) -> CFRatingChange {
+        CFRatingChange {
+            contestId: 7,
+            contestName: "Round".to_string(),
+            handle: handle.to_string(),
+            rank,
+            ratingUpdateTimeSeconds: time,
+            oldRating: 1500,
+            newRating: 1500,
         }
     }
+
+    #[test]
+    fn validation() {
+        let changes = vec![
+            change("a", 1, 10),
+            change("b", 1, 10),
+            change("a", 3, 20),
+            change("c", 4, 10),
+        ];
+
+        let contest = Contest::validate(&changes, &ValidationPolicy::default(), false).unwrap();
+        assert_eq!(contest.time_seconds, 10);
+        assert_eq!(
+            contest.standings,
+            vec![
+                ("a_clone".to_string(), 0, 1),
+                ("b".to_string(), 0, 1),
+                ("a".to_string(), 2, 2),
+                ("c".to_string(), 3, 3)
+            ]
+        );
+        let anomalies: Vec<_> = contest.issues.iter().map(|issue| issue.anomaly).collect();
+        assert_eq!(
+            anomalies,
+            vec![Anomaly::InconsistentTime, Anomaly::DuplicateUser]
+        );
+
+        let mut policy = ValidationPolicy::default();
+        policy.set(Anomaly::DuplicateUser, Action::Error);
+        assert!(Contest::validate(&changes, &policy, false).is_err());
+        assert!(Contest::validate(&changes, &ValidationPolicy::strict(), false).is_err());
+
+        policy.set(Anomaly::DuplicateUser, Action::Fix);
+        let contest = Contest::validate(&changes, &policy, false).unwrap();
+        assert_eq!(contest.standings[0].0, "a_clone");
+        assert_eq!(contest.issues.len(), 1);
+        assert_eq!(contest.issues[0].anomaly, Anomaly::InconsistentTime);
+    }
+
+    #[test]
+    fn invalid_ranks() {
+        let changes = vec![change("a", 1, 10), change("b", 1, 10), change("c", 2, 10)];
+
+        assert!(Contest::validate(&changes, &ValidationPolicy::default(), false).is_err());
+
+        let mut policy = ValidationPolicy::default();
+        policy.set(Anomaly::InvalidRank, Action::Warn);
+        let contest = Contest::validate(&changes, &policy, false).unwrap();
+        assert_eq!(contest.standings[2], ("c".to_string(), 2, 2));
+        assert_eq!(contest.issues.len(), 1);
+        assert_eq!(contest.issues[0].anomaly, Anomaly::InvalidRank);
+    }
+
+    #[test]
+    fn party_ranks() {
+        let policy = ValidationPolicy::default();
+        let ranks = |ranks: &[usize]| -> Vec<CFRatingChange> {
+            ranks
+                .iter()
+                .enumerate()
+                .map(|(i, &rank)| change(&i.to_string(), rank, 10))
+                .collect()
+        };
+
+        // a team of two wins, the next party is second
+        let contest = Contest::validate(&ranks(&[1, 1, 2]), &policy, true).unwrap();
+        assert_eq!(contest.standings[2], ("2".to_string(), 2, 2));
+        assert!(Contest::validate(&ranks(&[1, 1, 2]), &policy, false).is_err());
+
+        assert!(Contest::validate(&ranks(&[1, 1, 3]), &policy, true).is_ok());
+        assert!(Contest::validate(&ranks(&[1, 1, 4]), &policy, true).is_err());
+        assert!(Contest::validate(&ranks(&[2, 2, 3]), &policy, true).is_err());
+        assert!(Contest::validate(&ranks(&[1, 2, 1]), &policy, true).is_err());
+    }
+
+    #[test]
+    fn foreign_rows() {
+        let mut changes = vec![change("a", 1, 10), change("b", 2, 10), change("c", 3, 10)];
+        changes[1].contestId = 8;
+        changes[2].contestName = "Other Round".to_string();
+
+        assert!(Contest::validate(&changes, &ValidationPolicy::default(), false).is_err());
+
+        let mut policy = ValidationPolicy::default();
+        policy
+            .set(Anomaly::InconsistentId, Action::Warn)
+            .set(Anomaly::InconsistentName, Action::Warn);
+        let contest = Contest::validate(&changes, &policy, false).unwrap();
+        assert_eq!(contest.standings, vec![("a".to_string(), 0, 0)]);
+        assert_eq!(contest.old_ratings.len(), 1);
+        let anomalies: Vec<_> = contest.issues.iter().map(|issue| issue.anomaly).collect();
+        assert_eq!(
+            anomalies,
+            vec![Anomaly::InconsistentId, Anomaly::InconsistentName]
+        );
+    }
 }
@@ -0,0 +1,581 @@
+use read_codeforces::{Anomaly, Client, Error, ValidationPolicy};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Serves the given `(status, body)` responses to the consecutive connections,
+/// returns the base URL and the received requests
+fn serve(responses: Vec<(u16, String)>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/api", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let received = requests.clone();
+
+    thread::spawn(move || {
+        for (status, body) in responses {
+            let (mut stream, _) = listener.accept().unwrap();
+
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let len = stream.read(&mut buf).unwrap();
+                if len == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..len]);
+            }
+            received
+                .lock()
+                .unwrap()
+                .push(String::from_utf8(request).unwrap());
+
+            let response = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+
+    (url, requests)
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "read_codeforces_client_{}_{}",
+        name,
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn client(url: &str) -> Client {
+    Client::builder()
+        .base_url(url)
+        .user_agent("mock-test")
+        .delay(Duration::from_millis(0))
+        .requests_per_second(1000.)
+        .max_attempts(1)
+        .timeout(Duration::from_millis(500))
+        .build()
+        .unwrap()
+}
+
+fn retrying_client(url: &str) -> Client {
+    Client::builder()
+        .base_url(url)
+        .requests_per_second(1000.)
+        .backoff(Duration::from_millis(1))
+        .max_attempts(3)
+        .timeout(Duration::from_millis(500))
+        .build()
+        .unwrap()
+}
+
+fn rating_change(handle: &str, rank: usize) -> String {
+    format!(
+        r#"{{"contestId": 5, "contestName": "Mock Round", "handle": "{}", "rank": {},
+            "ratingUpdateTimeSeconds": 1000, "oldRating": 1500, "newRating": 1510}}"#,
+        handle, rank
+    )
+}
+
+#[test]
+fn contest_is_fetched_and_cached() {
+    let body = format!(
+        r#"{{"status": "OK", "result": [{}, {}]}}"#,
+        rating_change("alice", 1),
+        rating_change("bob", 2)
+    );
+    let (url, requests) = serve(vec![(200, body)]);
+    let dir = temp_dir("cached");
+
+    let contest = client(&url).contest(&dir, 5).unwrap();
+    let offline = Client::builder()
+        .offline(true)
+        .build()
+        .unwrap()
+        .contest(&dir, 5);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(contest.name, "Mock Round");
+    assert_eq!(
+        contest.standings,
+        vec![("alice".to_string(), 0, 0), ("bob".to_string(), 1, 1)]
+    );
+    assert_eq!(contest.old_ratings["bob"], 1500);
+    assert_eq!(offline.unwrap().standings, contest.standings);
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert!(requests[0].starts_with("GET /api/contest.ratingChanges?contestId=5 "));
+    assert!(requests[0].to_lowercase().contains("user-agent: mock-test"));
+}
+
+#[test]
+fn cached_contest_is_revalidated() {
+    let body = format!(
+        r#"{{"status": "OK", "result": [{}, {}]}}"#,
+        rating_change("alice", 1),
+        rating_change("alice", 2)
+    );
+    let (url, _) = serve(vec![(200, body)]);
+    let dir = temp_dir("revalidated");
+
+    let contest = client(&url).contest(&dir, 5).unwrap();
+    let strict = Client::builder()
+        .offline(true)
+        .validation(ValidationPolicy::strict())
+        .build()
+        .unwrap()
+        .contest(&dir, 5);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(contest.issues.len(), 1);
+    assert_eq!(contest.issues[0].anomaly, Anomaly::DuplicateUser);
+    assert!(matches!(strict, Err(Error::InvalidData(_))));
+}
+
+#[test]
+fn teams_are_fetched() {
+    let changes = format!(
+        r#"{{"status": "OK", "result": [{}, {}, {}]}}"#,
+        rating_change("alice", 1),
+        rating_change("bob", 1),
+        rating_change("carol", 2)
+    );
+    let standings = r#"{"status": "OK", "result": {"rows": [
+        {"party": {"members": [{"handle": "alice"}, {"handle": "bob"}], "participantType": "CONTESTANT"}},
+        {"party": {"members": [{"handle": "carol"}], "participantType": "CONTESTANT"}},
+        {"party": {"members": [{"handle": "x"}, {"handle": "y"}], "participantType": "PRACTICE"}}
+    ]}}"#;
+    let (url, requests) = serve(vec![(200, changes), (200, standings.to_string())]);
+    let dir = temp_dir("teams");
+
+    let contest = client(&url).contest_with_teams(&dir, 5);
+    let cached = dir.join("5_standings.json").exists();
+    // ranks count parties, so the rows alone are inconsistent
+    let individual = client(&url).contest(&dir, 5);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let contest = contest.unwrap();
+    assert_eq!(
+        contest.teams,
+        vec![vec!["alice".to_string(), "bob".to_string()]]
+    );
+    assert_eq!(contest.standings[2], ("carol".to_string(), 2, 2));
+    assert!(contest.issues.is_empty());
+    assert!(matches!(individual, Err(Error::InvalidData(_))));
+    assert!(cached);
+    assert!(requests.lock().unwrap()[1]
+        .starts_with("GET /api/contest.standings?contestId=5&showUnofficial=false "));
+}
+
+#[test]
+fn failed_response() {
+    let body = r#"{"status": "FAILED", "comment": "contestId: Contest with id 5 not found"}"#;
+    let (url, _) = serve(vec![(400, body.to_string())]);
+    let dir = temp_dir("failed");
+
+    let contest = client(&url).contest(&dir, 5);
+    let cached = dir.join("5.json").exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(matches!(contest, Err(Error::Api(comment)) if comment.contains("not found")));
+    assert!(!cached);
+}
+
+#[test]
+fn rate_limit_response() {
+    let body = r#"{"status": "FAILED", "comment": "Call limit exceeded"}"#;
+    let (url, _) = serve(vec![(503, body.to_string())]);
+    let dir = temp_dir("rate_limit");
+
+    let contest = client(&url).contest(&dir, 5);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(matches!(contest, Err(Error::Api(comment)) if comment == "Call limit exceeded"));
+}
+
+#[test]
+fn retries() {
+    let ok = format!(
+        r#"{{"status": "OK", "result": [{}]}}"#,
+        rating_change("alice", 1)
+    );
+    let (url, requests) = serve(vec![
+        (
+            503,
+            r#"{"status": "FAILED", "comment": "Call limit exceeded"}"#.to_string(),
+        ),
+        (502, "<html>Bad Gateway</html>".to_string()),
+        (200, ok),
+    ]);
+    let dir = temp_dir("retries");
+
+    let contest = retrying_client(&url).contest(&dir, 5);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(contest.unwrap().name, "Mock Round");
+    assert_eq!(requests.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn retries_exhausted() {
+    let body = r#"{"status": "FAILED", "comment": "Call limit exceeded"}"#;
+    let (url, requests) = serve(vec![(503, body.to_string()); 3]);
+    let dir = temp_dir("exhausted");
+
+    let contest = retrying_client(&url).contest(&dir, 5);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    match contest {
+        Err(Error::RetriesExhausted { attempts, last }) => {
+            assert_eq!(attempts, 3);
+            assert!(matches!(*last, Error::Api(comment) if comment == "Call limit exceeded"));
+        }
+        _ => panic!("Expected exhausted retries"),
+    }
+    assert_eq!(requests.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn request_budget() {
+    let ok = format!(
+        r#"{{"status": "OK", "result": [{}]}}"#,
+        rating_change("alice", 1)
+    );
+    let (url, _) = serve(vec![(200, ok); 3]);
+    let dir = temp_dir("budget");
+    let client = Client::builder()
+        .base_url(url)
+        .requests_per_second(10.)
+        .build()
+        .unwrap();
+
+    let start = Instant::now();
+    for id in 5..8 {
+        client.contest(&dir, id).unwrap();
+    }
+    let elapsed = start.elapsed();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(elapsed >= Duration::from_millis(200));
+}
+
+#[test]
+fn cache_misses_are_spaced() {
+    let ok = format!(
+        r#"{{"status": "OK", "result": [{}]}}"#,
+        rating_change("alice", 1)
+    );
+    let (url, _) = serve(vec![(200, ok); 2]);
+    let dir = temp_dir("spaced");
+    let client = Client::builder()
+        .base_url(url)
+        .requests_per_second(4.)
+        .build()
+        .unwrap();
+
+    let start = Instant::now();
+    client.contest(&dir, 5).unwrap();
+    client.contest(&dir, 6).unwrap();
+    let elapsed = start.elapsed();
+    // hits are not requests, so they are not delayed
+    client.contest(&dir, 5).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(elapsed >= Duration::from_millis(250));
+}
+
+#[test]
+fn invalid_response() {
+    let (url, _) = serve(vec![(
+        200,
+        "<html>Codeforces is temporarily unavailable</html>".to_string(),
+    )]);
+    let dir = temp_dir("invalid");
+
+    let contest = client(&url).contest(&dir, 5);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(matches!(contest, Err(Error::Http(_))));
+}
+
+#[test]
+fn invalid_response_is_not_retried() {
+    let (url, requests) = serve(vec![(200, "{}".to_string())]);
+    let dir = temp_dir("not_retried");
+
+    let contest = retrying_client(&url).contest(&dir, 5);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(matches!(contest, Err(Error::Http(_))));
+    assert_eq!(requests.lock().unwrap().len(), 1);
+}
+
+/// Accepts connections and never responds
+fn hang() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/api", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let mut streams = Vec::new();
+        for stream in listener.incoming() {
+            streams.push(stream);
+        }
+    });
+    url
+}
+
+#[test]
+fn timeout() {
+    let url = hang();
+    let dir = temp_dir("timeout");
+
+    let contest = client(&url).contest(&dir, 5);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(matches!(contest, Err(Error::Http(_))));
+}
+
+#[test]
+fn timeout_is_retried() {
+    let url = hang();
+    let dir = temp_dir("timeout_retried");
+    let client = Client::builder()
+        .base_url(url)
+        .requests_per_second(1000.)
+        .backoff(Duration::from_millis(1))
+        .timeout(Duration::from_millis(200))
+        .max_attempts(2)
+        .build()
+        .unwrap();
+
+    let contest = client.contest(&dir, 5);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(matches!(
+        contest,
+        Err(Error::RetriesExhausted { attempts: 2, last }) if matches!(*last, Error::Http(_))
+    ));
+}
+
+const EMPTY: &str = r#"{"status": "OK", "result": []}"#;
+
+fn contest_list(contests: &[(usize, &str, u64)]) -> String {
+    let contests: Vec<String> = contests
+        .iter()
+        .map(|(id, phase, start)| {
+            format!(
+                r#"{{"id": {}, "name": "Round {}", "phase": "{}", "startTimeSeconds": {}}}"#,
+                id, id, phase, start
+            )
+        })
+        .collect();
+    format!(r#"{{"status": "OK", "result": [{}]}}"#, contests.join(", "))
+}
+
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[test]
+fn new_rated_contests() {
+    let list = contest_list(&[
+        (6, "CODING", 400),
+        (5, "FINISHED", 300),
+        (2, "FINISHED", 200),
+        (1, "FINISHED", 100),
+        (0, "FINISHED", 50),
+    ]);
+    let changes = format!(
+        r#"{{"status": "OK", "result": [{}]}}"#,
+        rating_change("alice", 1)
+    );
+    let (url, requests) = serve(vec![
+        (200, list.clone()),
+        (200, EMPTY.to_string()),
+        (200, EMPTY.to_string()),
+        (200, changes),
+        (200, list),
+    ]);
+    let dir = temp_dir("discovery");
+
+    let update = client(&url).update_contest_ids(&dir, &[1]).unwrap();
+    let cached = (dir.join("2.json").exists(), dir.join("5.json").exists());
+    let unrated = std::fs::read_to_string(dir.join("unrated.json")).unwrap();
+    // old contests without rating changes are not requested again
+    let second = client(&url).update_contest_ids(&dir, &update.ids).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(update.ids, vec![1, 5]);
+    assert!(update.failed.is_empty());
+    assert_eq!(cached, (false, true));
+    assert_eq!(
+        serde_json::from_str::<Vec<usize>>(&unrated).unwrap(),
+        vec![0, 2]
+    );
+    assert_eq!(second.ids, vec![1, 5]);
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 5);
+    assert!(requests[0].starts_with("GET /api/contest.list?gym=false "));
+    assert!(requests[1].starts_with("GET /api/contest.ratingChanges?contestId=0 "));
+    assert!(requests[2].starts_with("GET /api/contest.ratingChanges?contestId=2 "));
+}
+
+fn changes_of(contest_id: usize, time: usize) -> String {
+    format!(
+        r#"[{{"contestId": {}, "contestName": "Round {}", "handle": "alice", "rank": 1,
+            "ratingUpdateTimeSeconds": {}, "oldRating": 1500, "newRating": 1510}}]"#,
+        contest_id, contest_id, time
+    )
+}
+
+#[test]
+fn older_contests_are_merged() {
+    let list = contest_list(&[
+        (5, "FINISHED", 300),
+        (3, "FINISHED", 250),
+        (2, "FINISHED", 200),
+        (1, "FINISHED", 100),
+        (0, "FINISHED", 50),
+    ]);
+    let (url, _) = serve(vec![
+        (200, list),
+        (
+            200,
+            format!(r#"{{"status": "OK", "result": {}}}"#, changes_of(0, 150)),
+        ),
+        (
+            200,
+            format!(r#"{{"status": "OK", "result": {}}}"#, changes_of(5, 3000)),
+        ),
+    ]);
+    let dir = temp_dir("merged");
+    // contest 1 is cached, contest 2 and 3 are placed by their start times
+    std::fs::write(dir.join("1.json"), changes_of(1, 120)).unwrap();
+
+    let update = client(&url).update_contest_ids(&dir, &[1, 2, 3]).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // contest 0 started before contest 1, but was rated after it
+    assert_eq!(update.ids, vec![1, 0, 2, 3, 5]);
+    assert_eq!(update.new, vec![0, 5]);
+}
+
+#[test]
+fn pending_contests() {
+    let day = 24 * 60 * 60;
+    let list = contest_list(&[
+        (1, "FINISHED", now() - 30 * day),
+        (2, "FINISHED", now() - 2 * day),
+        (5, "FINISHED", now() - day),
+    ]);
+    let changes = |id: usize, time: u64| {
+        format!(
+            r#"{{"status": "OK", "result": {}}}"#,
+            changes_of(id, time as usize)
+        )
+    };
+    let (url, requests) = serve(vec![
+        (200, list.clone()),
+        (200, EMPTY.to_string()),
+        (200, changes(5, now())),
+        (200, list),
+        (200, changes(2, now() + 1)),
+    ]);
+    let dir = temp_dir("pending");
+
+    // the ratings of contest 2 are published only after contest 5
+    let first = client(&url).update_contest_ids(&dir, &[1]).unwrap();
+    let second = client(&url).update_contest_ids(&dir, &first.ids).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(first.ids, vec![1, 5]);
+    assert_eq!(second.ids, vec![1, 5, 2]);
+    assert!(requests.lock().unwrap()[4].starts_with("GET /api/contest.ratingChanges?contestId=2 "));
+}
+
+#[test]
+fn failed_contests() {
+    let list = contest_list(&[
+        (1, "FINISHED", 100),
+        (2, "FINISHED", 200),
+        (5, "FINISHED", 300),
+    ]);
+    let changes = format!(
+        r#"{{"status": "OK", "result": [{}]}}"#,
+        rating_change("alice", 1)
+    );
+    let (url, _) = serve(vec![
+        (200, list),
+        (
+            400,
+            r#"{"status": "FAILED", "comment": "contestId: Rating changes are unavailable"}"#
+                .to_string(),
+        ),
+        (200, changes),
+    ]);
+    let dir = temp_dir("failed_contests");
+
+    let update = client(&url).update_contest_ids(&dir, &[1]).unwrap();
+    let unrated = std::fs::read_to_string(dir.join("unrated.json")).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(update.ids, vec![1, 5]);
+    assert_eq!(update.failed.len(), 1);
+    assert!(matches!(&update.failed[0], (2, Error::Api(_))));
+    assert_eq!(
+        serde_json::from_str::<Vec<usize>>(&unrated).unwrap(),
+        Vec::<usize>::new()
+    );
+}
+
+#[test]
+fn renamed_handles() {
+    let (url, requests) = serve(vec![
+        (
+            400,
+            r#"{"status": "FAILED", "comment": "handles: User with handle gone not found"}"#
+                .to_string(),
+        ),
+        (
+            200,
+            r#"{"status": "OK", "result": [{"handle": "alice"}, {"handle": "bob"}]}"#.to_string(),
+        ),
+    ]);
+    let handles = vec![
+        "alice".to_string(),
+        "gone".to_string(),
+        "old_bob".to_string(),
+    ];
+
+    let renamed = client(&url).current_handles(&handles).unwrap();
+
+    assert_eq!(renamed.len(), 1);
+    assert_eq!(renamed["old_bob"], "bob");
+    assert!(requests.lock().unwrap()[1].starts_with("GET /api/user.info?handles=alice%3Bold_bob "));
+}
+
+#[test]
+fn missing_users() {
+    let (url, _) = serve(vec![(
+        200,
+        r#"{"status": "OK", "result": [{"handle": "alice"}]}"#.to_string(),
+    )]);
+    let handles = vec!["alice".to_string(), "old_bob".to_string()];
+
+    let result = client(&url).current_handles(&handles);
+
+    assert!(matches!(result, Err(Error::InvalidData(_))));
+}
@@ -0,0 +1,263 @@
+use crate::nodes::{Message, TreeNode, ValueNode, FuncNode};
+use std::cell::RefCell;
+use std::rc::Weak;
+
+/// Index of a node in its `FactorGraph`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+enum Node {
+    Variable(Box<dyn ValueNode>),
+    Factor(Box<dyn TreeNode>),
+}
+
+impl Node {
+    fn infer(&mut self) {
+        match self {
+            Node::Variable(node) => node.infer(),
+            Node::Factor(node) => node.infer(),
+        }
+    }
+}
+
+/// Order in which the messages are passed
+#[derive(Clone)]
+pub enum Schedule {
+    /// Calls `infer` of the nodes in the given order
+    Nodes(Vec<NodeId>),
+    Sequence(Vec<Schedule>),
+    /// Repeats `body` until the messages on `watch` change by less than `eps` during a round
+    /// or `max_rounds` rounds are made. The first round is made unless `max_rounds` is `Some(0)`,
+    /// with an empty `watch` it is the only one.
+    Loop {
+        body: Box<Schedule>,
+        watch: Vec<Weak<RefCell<(Message, Message)>>>,
+        eps: f64,
+        max_rounds: Option<usize>,
+    },
+}
+
+/// Owns the nodes of a factor graph and runs schedules over them
+#[derive(Default)]
+pub struct FactorGraph {
+    nodes: Vec<Node>,
+}
+
+
+fn message_distance(a: &Message, b: &Message) -> f64 {
+    if a.is_proper() != b.is_proper() {
+        return f64::INFINITY;
+    }
+
+    // compare in the same units as ratings: mean and (signed for improper messages) deviation
+    let deviation = |m: &Message| m.sigma2().signum() * m.sigma2().abs().sqrt();
+
+    f64::max(f64::abs(a.mu() - b.mu()), f64::abs(deviation(a) - deviation(b)))
+}
+
+
+fn check_convergence(a: &[Weak<RefCell<(Message, Message)>>],
+                     b: &[(Message, Message)]) -> f64 {
+    if a.len() != b.len() {
+        return f64::INFINITY;
+    }
+
+    let mut ret = 0.;
+
+    for i in 0..a.len() {
+        let edge = a[i].upgrade().expect("Watched edge outlived its graph");
+        ret = f64::max(ret,
+                       f64::max(
+                           message_distance(&RefCell::borrow(&edge).0, &b[i].0),
+                           message_distance(&RefCell::borrow(&edge).1, &b[i].1),
+                       ));
+    }
+
+    ret
+}
+
+
+impl FactorGraph {
+    pub fn new() -> Self {
+        FactorGraph { nodes: Vec::new() }
+    }
+
+    pub fn add_variable(&mut self, node: impl ValueNode + 'static) -> NodeId {
+        self.nodes.push(Node::Variable(Box::new(node)));
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Adds a factor connected to the given distinct variables
+    pub fn add_factor<T: FuncNode + 'static>(&mut self, neighbours: &[NodeId]) -> NodeId {
+        self.add_factor_with(neighbours, T::new)
+    }
+
+    /// Adds a factor built by `build` from the given distinct variables, in the same order
+    pub fn add_factor_with<T, F>(&mut self, neighbours: &[NodeId], build: F) -> NodeId
+        where T: TreeNode + 'static,
+              F: FnOnce(&mut [&mut dyn ValueNode]) -> T {
+        let mut variables: Vec<Option<&mut dyn ValueNode>> = self.nodes.iter_mut()
+            .map(|node| match node {
+                Node::Variable(node) => Some(&mut **node as &mut dyn ValueNode),
+                Node::Factor(_) => None,
+            })
+            .collect();
+
+        let mut tmp = Vec::with_capacity(neighbours.len());
+        for id in neighbours {
+            tmp.push(variables[id.0].take().expect("Neighbour is not a variable or repeats"));
+        }
+
+        let factor = build(&mut tmp);
+        self.nodes.push(Node::Factor(Box::new(factor)));
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Creates a loose edge of a variable, e.g. to feed a prior into it
+    pub fn add_edge(&mut self, variable: NodeId) -> Weak<RefCell<(Message, Message)>> {
+        self.variable_mut(variable).add_edge()
+    }
+
+    /// Edges of a variable in the order of creation
+    pub fn edges(&self, variable: NodeId) -> Vec<Weak<RefCell<(Message, Message)>>> {
+        match &self.nodes[variable.0] {
+            Node::Variable(node) => node.edges(),
+            Node::Factor(_) => panic!("{:?} is not a variable", variable),
+        }
+    }
+
+    pub fn infer(&mut self, node: NodeId) {
+        self.nodes[node.0].infer();
+    }
+
+    /// Returns the total number of loop rounds made
+    pub fn run(&mut self, schedule: &Schedule) -> usize {
+        match schedule {
+            Schedule::Nodes(nodes) => {
+                for node in nodes {
+                    self.infer(*node);
+                }
+                0
+            }
+            Schedule::Sequence(schedules) => schedules.iter().map(|item| self.run(item)).sum(),
+            Schedule::Loop { body, watch, eps, max_rounds } => {
+                let mut rounds = 0;
+                let mut inner = 0;
+                let mut old = Vec::new();
+
+                while max_rounds.is_none_or(|max| rounds < max) &&
+                    (rounds == 0 || check_convergence(watch, &old) >= *eps) {
+                    old.clear();
+                    for edge in watch {
+                        old.push(RefCell::borrow(&edge.upgrade().unwrap()).clone());
+                    }
+                    rounds += 1;
+
+                    inner += self.run(body);
+                }
+
+                rounds + inner
+            }
+        }
+    }
+
+    fn variable_mut(&mut self, variable: NodeId) -> &mut dyn ValueNode {
+        match &mut self.nodes[variable.0] {
+            Node::Variable(node) => &mut **node,
+            Node::Factor(_) => panic!("{:?} is not a variable", variable),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nodes::{ProdNode, SumNode};
+    use distributions::normal::Gaussian;
+    use distributions::normal::natural::GaussianNat;
+    use std::convert::TryFrom;
+
+    /// Sends a fixed message, an example of a user-defined factor
+    struct Fixed {
+        edge: Weak<RefCell<(Message, Message)>>,
+        message: Message,
+    }
+
+    impl TreeNode for Fixed {
+        fn infer(&mut self) {
+            RefCell::borrow_mut(&self.edge.upgrade().unwrap()).0 = self.message.clone();
+        }
+    }
+
+    #[test]
+    fn custom_factor() {
+        let mut graph = FactorGraph::new();
+        let a = graph.add_variable(ProdNode::new());
+        let b = graph.add_variable(ProdNode::new());
+        let noise = graph.add_variable(ProdNode::new());
+
+        let fixed = graph.add_factor_with(&[a], |tmp| Fixed {
+            edge: tmp[0].add_edge(),
+            message: GaussianNat::from(Gaussian { mu: 10., sigma: 2. }),
+        });
+        let sum = graph.add_factor::<SumNode>(&[b, a, noise]);
+        RefCell::borrow_mut(&graph.edges(noise)[0].upgrade().unwrap()).1 =
+            GaussianNat::from(Gaussian { mu: 1., sigma: 1. });
+
+        let rounds = graph.run(&Schedule::Nodes(vec![fixed, a, sum]));
+        assert_eq!(rounds, 0);
+
+        let ans = Gaussian::try_from(&RefCell::borrow(&graph.edges(b)[0].upgrade().unwrap()).0).unwrap();
+        assert!(approx_eq!(f64, ans.mu, 11., epsilon = 1e-9));
+        assert!(approx_eq!(f64, ans.sigma, 5f64.sqrt(), epsilon = 1e-9));
+    }
+
+    #[test]
+    fn loop_limit() {
+        /// Never converges
+        struct Drift {
+            edge: Weak<RefCell<(Message, Message)>>,
+        }
+
+        impl TreeNode for Drift {
+            fn infer(&mut self) {
+                let edge = self.edge.upgrade().unwrap();
+                let mu = RefCell::borrow(&edge).0.mu();
+                RefCell::borrow_mut(&edge).0 = GaussianNat::from(Gaussian { mu: mu + 1., sigma: 1. });
+            }
+        }
+
+        let mut graph = FactorGraph::new();
+        let a = graph.add_variable(ProdNode::new());
+        let drift = graph.add_factor_with(&[a], |tmp| Drift { edge: tmp[0].add_edge() });
+
+        let rounds = graph.run(&Schedule::Loop {
+            body: Box::new(Schedule::Nodes(vec![drift])),
+            watch: graph.edges(a),
+            eps: 1e-3,
+            max_rounds: Some(7),
+        });
+
+        assert_eq!(rounds, 7);
+    }
+
+    #[test]
+    fn loop_edge_cases() {
+        let mut graph = FactorGraph::new();
+        let a = graph.add_variable(ProdNode::new());
+        let fixed = graph.add_factor_with(&[a], |tmp| Fixed {
+            edge: tmp[0].add_edge(),
+            message: GaussianNat::from(Gaussian { mu: 10., sigma: 2. }),
+        });
+        let body = Box::new(Schedule::Nodes(vec![fixed]));
+
+        let rounds = graph.run(&Schedule::Loop { body: body.clone(), watch: graph.edges(a), eps: 1e-3, max_rounds: Some(0) });
+        assert_eq!(rounds, 0);
+        assert!(RefCell::borrow(&graph.edges(a)[0].upgrade().unwrap()).0.is_uniform());
+
+        let rounds = graph.run(&Schedule::Loop { body, watch: Vec::new(), eps: 1e-3, max_rounds: None });
+        assert_eq!(rounds, 1);
+        assert!(approx_eq!(f64, RefCell::borrow(&graph.edges(a)[0].upgrade().unwrap()).0.mu(), 10.));
+    }
+}
@@ -2,15 +2,20 @@
 #[macro_use]
 extern crate float_cmp;
 
-mod nodes;
+pub mod nodes;
+pub mod graph;
+pub mod source;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use distributions::normal::Gaussian;
-use nodes::{ProdNode, LeqNode, GreaterNode, SumNode, TreeNode, ValueNode, FuncNode};
+use distributions::normal::natural::GaussianNat;
+use nodes::{ProdNode, LeqNode, GreaterNode, SumNode, WeightedSumNode, MixtureSumNode};
+use graph::{FactorGraph, NodeId, Schedule};
 use std::cell::{RefCell};
+use std::convert::TryFrom;
+use std::fmt;
 use std::rc::{Rc, Weak};
-use std::f64::INFINITY;
 
 // performance sigma
 const BETA: f64 = 200.;
@@ -24,243 +29,738 @@ const SIGMA: f64 = MU / 3.;
 const CONVERGENCE_EPS: f64 = 2e-4;
 // defines sigma growth per second
 const SIGMA_GROWTH: f64 = 0.01;
+// default sigma of a category component of skill
+const CATEGORY_SIGMA: f64 = SIGMA / 3.;
 
 pub type PlayerRating = Gaussian;
-type Message = nodes::Message;
 pub type Player = String;
 pub type Team = Vec<Player>;
 pub type ContestPlace = Vec<Team>;
 pub type Contest = Vec<ContestPlace>;
 pub type Rating = HashMap<Player, PlayerRating>;
 pub type RatingHistory = HashMap<Player, Vec<(PlayerRating, usize)>>;
+pub type Category = String;
 
 const DEFAULT_PLAYER_RATING: PlayerRating = PlayerRating {
     mu: MU,
     sigma: SIGMA,
 };
 
-fn load_rating(old: &RatingHistory, new: &mut Rating, contest: &Contest, when: usize) {
-    for place in &contest[..] {
-        for team in &place[..] {
-            for player in &team[..] {
-                let curr = old.get(player).cloned().unwrap_or(vec![(DEFAULT_PLAYER_RATING, when)]);
-                let mut add = curr.last().unwrap().clone();
-                assert!(add.1 <= when);
-                add.0.sigma = f64::min(SIGMA, (add.0.sigma.powi(2) +
-                    (when - add.1) as f64 * SIGMA_GROWTH.powi(2)).sqrt());
-                new.insert(player.to_string(), add.0);
-            }
-        }
+const DEFAULT_CATEGORY_RATING: PlayerRating = PlayerRating {
+    mu: 0.,
+    sigma: CATEGORY_SIGMA,
+};
+
+
+/// Optional information about a contest which is not expressed by the standings
+#[derive(Clone, Debug, Default)]
+pub struct ContestInfo {
+    /// Known performance offset of every team in the same layout as `Contest`, empty if there are none
+    pub team_bias: Vec<Vec<f64>>,
+    /// Known performance offset of a player, added to the performance of their team
+    pub player_bias: HashMap<Player, f64>,
+    /// Out of competition players: they take part in inference, but their ratings are not updated
+    pub frozen: HashSet<Player>,
+    /// Players whose place does not reflect their performance, handled according to `Config`
+    pub status: HashMap<Player, Status>,
+}
+
+impl ContestInfo {
+    fn bias(&self, contest: &Contest, place: usize, team: usize) -> f64 {
+        let team_bias = self.team_bias.get(place).and_then(|place| place.get(team)).cloned().unwrap_or(0.);
+        let player_bias: f64 = contest[place][team].iter()
+            .filter_map(|player| self.player_bias.get(player))
+            .sum();
+
+        team_bias + player_bias
     }
 }
 
 
-fn update_rating(old: &Rating, new: &mut RatingHistory, contest: &Contest, when: usize) {
-    for place in &contest[..] {
-        for team in &place[..] {
-            for player in &team[..] {
-                new.entry(player.clone()).or_insert(Vec::new()).push((old.get(player).unwrap().clone(), when));
-            }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Status {
+    /// Registered, but did not submit anything
+    NoShow,
+    Disqualified,
+}
+
+/// Parses the names used in contest files: `no_show` and `disqualified`
+impl std::str::FromStr for Status {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "no_show" => Ok(Status::NoShow),
+            "disqualified" => Ok(Status::Disqualified),
+            _ => Err(format!("Unknown status {:?}", s)),
         }
     }
 }
 
 
-fn gen_team_message<T, K: Clone>(places: &Vec<Vec<T>>, default: &K) -> Vec<Vec<K>> {
-    let mut ret: Vec<Vec<K>> = Vec::with_capacity(places.len());
+/// What to do with a player who has a `Status`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusPolicy {
+    /// Exclude from the contest as if they have not registered
+    Ignore,
+    /// Rank below everyone else, tied with the other such players
+    PlaceLast,
+    /// Exclude from inference, but record the growth of sigma since their previous contest
+    SigmaOnly,
+}
 
-    for place in places {
-        ret.push(vec![default.clone(); place.len()]);
+
+pub type PriorFn = dyn Fn(&Player) -> Option<PlayerRating>;
+
+/// Initial rating of a player seen for the first time, `None` falls back to the default one
+#[derive(Clone)]
+pub struct PriorProvider(pub Rc<PriorFn>);
+
+impl PriorProvider {
+    pub fn new(prior: impl Fn(&Player) -> Option<PlayerRating> + 'static) -> Self {
+        PriorProvider(Rc::new(prior))
     }
+}
 
-    ret
+impl fmt::Debug for PriorProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PriorProvider")
+    }
 }
 
 
-fn gen_player_message<T, K: Clone>(places: &Vec<Vec<Vec<T>>>, default: &K) -> Vec<Vec<Vec<K>>> {
-    let mut ret = Vec::with_capacity(places.len());
+// the rating of new Codeforces accounts starts from 0 instead of 1500 since May 2020
+const CF_DEFLATION_START: usize = 1588291200;
+// the displayed rating of new accounts is lower than the real one by up to this much
+const CF_MAX_DEFLATION: f64 = 1400.;
+
+/// Initial rating of a player with the given Codeforces rating before a contest at time `when`,
+/// `None` for newcomers, so they get the default prior: Codeforces reports them as 1500 before
+/// May 2020 and as 0 since then. Both scales are Elo-like, but we trust the external rating only partially.
+///
+/// Since May 2020 the displayed rating of a player with few contests is deflated by an unknown amount
+/// of up to `CF_MAX_DEFLATION`, so such a rating gets the middle of that range and the default sigma.
+///
+/// Nothing calls this by default, see `source::codeforces_priors` to seed from the contest records.
+pub fn codeforces_prior(rating: i32, when: usize) -> Option<PlayerRating> {
+    if rating <= 0 || (rating == 1500 && when < CF_DEFLATION_START) {
+        return None;
+    }
 
-    for place in places {
-        ret.push(Vec::with_capacity(place.len()));
+    Some(if when < CF_DEFLATION_START {
+        PlayerRating { mu: rating as f64, sigma: SIGMA / 2. }
+    } else {
+        PlayerRating { mu: rating as f64 + CF_MAX_DEFLATION / 2., sigma: SIGMA }
+    })
+}
 
-        for team in place {
-            ret.last_mut().unwrap().push(vec![default.clone(); team.len()]);
+
+/// Skill in a category is the general skill plus the component of that category
+#[derive(Clone, Debug, Default)]
+pub struct CategoryRatingHistory {
+    pub general: RatingHistory,
+    pub components: HashMap<Category, RatingHistory>,
+}
+
+
+/// Distribution of a player's performance around their skill
+#[derive(Clone, Debug)]
+pub enum PerformanceModel {
+    /// `skill + N(0, BETA)`
+    Gaussian,
+    /// With probability `outlier_probability` the noise is `outlier_scale` times wider than BETA,
+    /// so a disastrous result is partially explained as an outlier instead of moving the skill.
+    /// Inference panics unless `outlier_probability` is in `[0, 1]` and `outlier_scale` is positive.
+    Mixture { outlier_probability: f64, outlier_scale: f64 },
+}
+
+impl PerformanceModel {
+    /// Noise components as `(weight, distribution)`
+    fn noise(&self) -> Vec<(f64, Gaussian)> {
+        match self {
+            PerformanceModel::Gaussian => vec![(1., Gaussian { mu: 0., sigma: BETA })],
+            PerformanceModel::Mixture { outlier_probability, outlier_scale } => vec![
+                (1. - outlier_probability, Gaussian { mu: 0., sigma: BETA }),
+                (*outlier_probability, Gaussian { mu: 0., sigma: BETA * outlier_scale }),
+            ],
         }
     }
+}
+
 
-    ret
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Anchor {
+    Mean,
+    Median,
 }
 
 
-fn infer1(who: &mut Vec<impl TreeNode>) {
-    for item in who {
-        item.infer();
+/// Shifts the ratings of active players so that their `anchor` stays at `target`.
+///
+/// Only the entries computed by the current contest are shifted in place, the rating history of the
+/// other active players keeps what was computed at its time: their shift is applied when they are
+/// loaded for their next contest, see `current`.
+#[derive(Clone, Debug)]
+pub struct Normalization {
+    pub anchor: Anchor,
+    pub target: f64,
+    /// Players are active if they have a rating update during the last `active_period` seconds
+    pub active_period: usize,
+    /// Time of every normalisation so far with the sum of the shifts up to it
+    shifts: RefCell<Vec<(usize, f64)>>,
+}
+
+impl Normalization {
+    pub fn new(anchor: Anchor, target: f64, active_period: usize) -> Self {
+        Normalization { anchor, target, active_period, shifts: RefCell::default() }
     }
+
+    /// Sum of the shifts made up to `when` inclusive
+    fn total_shift(&self, when: usize) -> f64 {
+        let shifts = self.shifts.borrow();
+        let count = shifts.partition_point(|&(time, _)| time <= when);
+
+        if count == 0 { 0. } else { shifts[count - 1].1 }
+    }
+
+    /// Shift of a player not yet recorded in their history: the shifts made after their latest entry
+    /// while they were still active, up to `when` inclusive
+    pub fn pending_shift(&self, history: &[(PlayerRating, usize)], when: usize) -> f64 {
+        let last = history.last().unwrap().1;
+        let until = when.min(last.saturating_add(self.active_period));
+
+        if until <= last { 0. } else { self.total_shift(until) - self.total_shift(last) }
+    }
+
+    /// The latest rating of a player with the shifts made up to `when`
+    pub fn current(&self, history: &[(PlayerRating, usize)], when: usize) -> PlayerRating {
+        let mut rating = history.last().unwrap().0.clone();
+        rating.mu += self.pending_shift(history, when);
+        rating
+    }
+
+    /// Same as `leaderboard` with the ratings brought up to `when` by `current`
+    pub fn leaderboard(&self, rating_history: &RatingHistory, provisional: &Provisional, when: usize)
+                       -> Vec<LeaderboardEntry> {
+        let mut ans = leaderboard(rating_history, provisional);
+        for entry in &mut ans {
+            entry.rating = self.current(&rating_history[&entry.player], when);
+        }
+
+        sort_leaderboard(&mut ans);
+
+        ans
+    }
+}
+
+
+/// Ratings of the active players before normalisation
+#[derive(Clone, Debug)]
+pub struct PopulationStats {
+    pub when: usize,
+    pub active: usize,
+    pub mean: f64,
+    pub median: f64,
+    /// Added to the rating of every active player, zero if nothing was normalised
+    pub shift: f64,
+}
+
+
+/// Limits on the update of the general skill in a single contest, `None` disables a limit
+#[derive(Clone, Debug, Default)]
+pub struct Guards {
+    pub min_sigma: Option<f64>,
+    pub max_mu_change: Option<f64>,
+    pub max_sigma_reduction: Option<f64>,
 }
 
 
-fn infer2(who: &mut Vec<Vec<impl TreeNode>>) {
-    for item in who {
-        infer1(item);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GuardReason {
+    MinSigma,
+    MaxMuChange,
+    MaxSigmaReduction,
+}
+
+
+/// Update changed by `Guards`
+#[derive(Clone, Debug)]
+pub struct GuardRecord {
+    pub player: Player,
+    pub reasons: Vec<GuardReason>,
+    /// Result of inference
+    pub computed: PlayerRating,
+    pub applied: PlayerRating,
+}
+
+
+/// Diagnostics of a single contest
+#[derive(Clone, Debug, Default)]
+pub struct ContestReport {
+    /// Present if `Config::normalization` is set
+    pub population: Option<PopulationStats>,
+    /// Sorted by player
+    pub guarded: Vec<GuardRecord>,
+}
+
+
+/// Players are provisional until they have at least `min_contests` contests and,
+/// if `max_sigma` is set, their sigma is at most `max_sigma`
+#[derive(Clone, Debug)]
+pub struct Provisional {
+    pub min_contests: usize,
+    pub max_sigma: Option<f64>,
+}
+
+impl Default for Provisional {
+    fn default() -> Self {
+        Provisional {
+            min_contests: 10,
+            max_sigma: None,
+        }
+    }
+}
+
+impl Provisional {
+    pub fn is_provisional(&self, history: &[(PlayerRating, usize)]) -> bool {
+        history.len() < self.min_contests ||
+            self.max_sigma.is_some_and(|max| history.last().unwrap().0.sigma > max)
     }
 }
 
 
-fn infer3(who: &mut Vec<Vec<Vec<impl TreeNode>>>) {
-    for item in who {
-        infer2(item);
+#[derive(Clone, Debug)]
+pub struct LeaderboardEntry {
+    pub player: Player,
+    pub rating: PlayerRating,
+    pub contests: usize,
+    pub provisional: bool,
+}
+
+
+/// Settings of the rating system, `Config::default()` gives the original model
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub performance: PerformanceModel,
+    pub no_show: StatusPolicy,
+    pub disqualified: StatusPolicy,
+    /// Seeds the general skill of newcomers
+    pub prior: Option<PriorProvider>,
+    /// Applied to the general skill after every contest
+    pub normalization: Option<Normalization>,
+    pub guards: Guards,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            performance: PerformanceModel::Gaussian,
+            no_show: StatusPolicy::Ignore,
+            disqualified: StatusPolicy::PlaceLast,
+            prior: None,
+            normalization: None,
+            guards: Guards::default(),
+        }
     }
 }
 
+impl Config {
+    fn policy(&self, status: Status) -> StatusPolicy {
+        match status {
+            Status::NoShow => self.no_show,
+            Status::Disqualified => self.disqualified,
+        }
+    }
+}
 
-fn infer_ld(ld: &mut Vec<impl TreeNode>, l: &mut Vec<impl TreeNode>) {
-    for i in 0..ld.len() {
-        l[i].infer();
-        ld[i].infer();
+
+/// Rearranges the contest according to the statuses of the players.
+/// Returns the ranked contest, its info in the new layout and the players with sigma-only update.
+fn apply_statuses(config: &Config, contest: &Contest, info: &ContestInfo)
+                  -> (Contest, ContestInfo, Vec<Player>) {
+    let mut ranked = Contest::new();
+    let mut team_bias = Vec::new();
+    let mut last = ContestPlace::new();
+    let mut sigma_only = Vec::new();
+
+    for (i, place) in contest.iter().enumerate() {
+        let mut new_place = ContestPlace::new();
+        let mut new_bias = Vec::new();
+
+        for (j, team) in place.iter().enumerate() {
+            let mut new_team = Team::new();
+
+            for player in team {
+                match info.status.get(player).map(|status| config.policy(*status)) {
+                    None => new_team.push(player.clone()),
+                    Some(StatusPolicy::Ignore) => {}
+                    Some(StatusPolicy::PlaceLast) => last.push(vec![player.clone()]),
+                    Some(StatusPolicy::SigmaOnly) => sigma_only.push(player.clone()),
+                }
+            }
+
+            if !new_team.is_empty() {
+                new_place.push(new_team);
+                new_bias.push(info.team_bias.get(i).and_then(|place| place.get(j)).cloned().unwrap_or(0.));
+            }
+        }
+
+        if !new_place.is_empty() {
+            ranked.push(new_place);
+            team_bias.push(new_bias);
+        }
     }
-    l.last_mut().unwrap().infer();
-    for j in 0..ld.len() {
-        let i = ld.len() - 1 - j;
-        ld[i].infer();
-        l[i].infer();
+
+    if !last.is_empty() {
+        ranked.push(last);
     }
+
+    (ranked, ContestInfo { team_bias, ..info.clone() }, sigma_only)
 }
 
 
-fn check_convergence(a: &Vec<Rc<RefCell<(Message, Message)>>>,
-                     b: &Vec<(Message, Message)>) -> f64 {
-    if a.len() != b.len() {
-        return INFINITY;
+fn load_rating(old: &RatingHistory, new: &mut Rating, contest: &Contest, when: usize,
+               default: &PlayerRating, prior: Option<&PriorProvider>, normalization: Option<&Normalization>) {
+    for place in &contest[..] {
+        for team in &place[..] {
+            for player in &team[..] {
+                let curr = old.get(player).cloned().unwrap_or_else(|| {
+                    let initial = prior.and_then(|prior| (prior.0)(player)).unwrap_or_else(|| default.clone());
+                    vec![(initial, when)]
+                });
+                let mut add = curr.last().unwrap().clone();
+                assert!(add.1 <= when);
+                add.0.mu += normalization.map_or(0., |normalization| normalization.pending_shift(&curr, when));
+                add.0.sigma = f64::min(default.sigma, (add.0.sigma.powi(2) +
+                    (when - add.1) as f64 * SIGMA_GROWTH.powi(2)).sqrt());
+                new.insert(player.to_string(), add.0);
+            }
+        }
     }
+}
 
-    let mut ret = 0.;
 
-    for i in 0..a.len() {
-        ret = f64::max(ret,
-                       f64::max(
-                           f64::max(f64::abs(RefCell::borrow(&a[i]).0.mu - b[i].0.mu),
-                                    f64::abs(RefCell::borrow(&a[i]).0.sigma - b[i].0.sigma)),
-                           f64::max(f64::abs(RefCell::borrow(&a[i]).1.mu - b[i].1.mu),
-                                    f64::abs(RefCell::borrow(&a[i]).1.sigma - b[i].1.sigma)),
-                       ));
+fn update_rating(old: &Rating, new: &mut RatingHistory, contest: &Contest, info: &ContestInfo,
+                 when: usize) {
+    for place in &contest[..] {
+        for team in &place[..] {
+            for player in team.iter().filter(|player| !info.frozen.contains(*player)) {
+                new.entry(player.clone()).or_default().push((old.get(player).unwrap().clone(), when));
+            }
+        }
     }
+}
+
+
+impl Guards {
+    /// Caps the changes from `prior` to `posterior` and returns what has been capped
+    fn apply(&self, prior: &Rating, posterior: &mut Rating, info: &ContestInfo) -> Vec<GuardRecord> {
+        let mut records = Vec::new();
+
+        for (player, rating) in posterior.iter_mut().filter(|(player, _)| !info.frozen.contains(*player)) {
+            let before = &prior[player];
+            let computed = rating.clone();
+            let mut reasons = Vec::new();
+
+            if let Some(max) = self.max_mu_change {
+                if f64::abs(rating.mu - before.mu) > max {
+                    rating.mu = before.mu + max * f64::signum(rating.mu - before.mu);
+                    reasons.push(GuardReason::MaxMuChange);
+                }
+            }
+            if let Some(max) = self.max_sigma_reduction {
+                if rating.sigma < before.sigma - max {
+                    rating.sigma = before.sigma - max;
+                    reasons.push(GuardReason::MaxSigmaReduction);
+                }
+            }
+            if let Some(min) = self.min_sigma {
+                if rating.sigma < min {
+                    rating.sigma = min;
+                    reasons.push(GuardReason::MinSigma);
+                }
+            }
+
+            if !reasons.is_empty() {
+                records.push(GuardRecord { player: player.clone(), reasons, computed, applied: rating.clone() });
+            }
+        }
 
-    ret
+        records.sort_by(|a, b| a.player.cmp(&b.player));
+
+        records
+    }
 }
 
 
-fn inference(rating: &mut Rating, contest: &Contest) {
+/// Forward and backward pass over the chain of places
+fn ld_schedule(ld: &[NodeId], l: &[NodeId]) -> Schedule {
+    let mut order = Vec::with_capacity(2 * (ld.len() + l.len()));
+
+    for i in 0..ld.len() {
+        order.push(l[i]);
+        order.push(ld[i]);
+    }
+    order.push(*l.last().unwrap());
+    for i in (0..ld.len()).rev() {
+        order.push(ld[i]);
+        order.push(l[i]);
+    }
+
+    Schedule::Nodes(order)
+}
+
+
+/// Skill of every player is the sum of their ratings in `ratings`
+fn inference(config: &Config, ratings: &mut [Rating], contest: &Contest, info: &ContestInfo) {
     if contest.is_empty() {
         return;
     }
 
-    // could be optimized, written that way for simplicity
-    let mut s = gen_player_message(contest, &ProdNode::new());
-    let mut perf = gen_player_message(contest, &ProdNode::new());
-    let mut p = gen_player_message(contest, &ProdNode::new());
-    let mut t = gen_team_message(contest, &ProdNode::new());
-    let mut u = gen_team_message(contest, &LeqNode::new(EPS));
-    let mut l = vec![ProdNode::new(); contest.len()];
-    let mut d = vec![GreaterNode::new(2. * EPS); contest.len() - 1];
+    let mut graph = FactorGraph::new();
+    let mut c = Vec::new();
+    let mut cs = Vec::new();
+    let mut s = Vec::new();
     let mut sp = Vec::new();
+    let mut p = Vec::new();
     let mut pt = Vec::new();
+    let mut t = Vec::new();
     let mut tul = Vec::new();
+    let mut u = Vec::new();
+    let mut l = Vec::new();
     let mut ld = Vec::new();
+    let mut d = Vec::new();
     let mut players = Vec::new();
     let mut conv = Vec::new();
-    let mut old_conv = Vec::new();
-
-    for i in 0..contest.len() {
-        for j in 0..contest[i].len() {
-            for k in 0..contest[i][j].len() {
-                players.push((contest[i][j][k].clone(), s[i][j][k].add_edge()));
-                RefCell::borrow_mut(&players.last().unwrap().1.upgrade().unwrap()).0 =
-                    rating.get(&players.last().unwrap().0).unwrap().clone();
-
-                let mut tmp: Vec<&mut dyn ValueNode> = Vec::with_capacity(3);
-                tmp.push(&mut p[i][j][k]);
-                tmp.push(&mut s[i][j][k]);
-                tmp.push(&mut perf[i][j][k]);
-                sp.push(SumNode::new(&mut tmp));
-                RefCell::borrow_mut(perf[i][j][k].get_edges_mut().last_mut().unwrap()).1 = Gaussian { mu: 0., sigma: BETA };
-            }
 
-            let mut tt: Vec<&mut dyn ValueNode> = vec![&mut t[i][j]];
-            for pp in &mut p[i][j] {
-                tt.push(pp);
+    for (i, place) in contest.iter().enumerate() {
+        l.push(graph.add_variable(ProdNode::new()));
+
+        for (j, team) in place.iter().enumerate() {
+            let team_perf = graph.add_variable(ProdNode::new());
+            let mut team_sum = vec![team_perf];
+
+            for player in team {
+                let skill = graph.add_variable(ProdNode::new());
+                let mut components = vec![skill];
+
+                for (r, rating) in ratings.iter().enumerate() {
+                    let component = if ratings.len() == 1 {
+                        skill
+                    } else {
+                        graph.add_variable(ProdNode::new())
+                    };
+                    let prior = graph.add_edge(component);
+                    RefCell::borrow_mut(&prior.upgrade().unwrap()).0 =
+                        GaussianNat::from(rating.get(player).unwrap());
+                    players.push((r, player.clone(), prior));
+                    components.push(component);
+                }
+
+                if ratings.len() != 1 {
+                    cs.push(graph.add_factor::<SumNode>(&components));
+                    c.extend_from_slice(&components[1..]);
+                }
+
+                let player_perf = graph.add_variable(ProdNode::new());
+                match &config.performance {
+                    PerformanceModel::Gaussian => {
+                        let perf = graph.add_variable(ProdNode::new());
+                        sp.push(graph.add_factor::<SumNode>(&[player_perf, skill, perf]));
+                        RefCell::borrow_mut(&graph.edges(perf)[0].upgrade().unwrap()).1 =
+                            GaussianNat::from(Gaussian { mu: 0., sigma: BETA });
+                    }
+                    model => {
+                        sp.push(graph.add_factor_with(&[player_perf, skill], |tmp| {
+                            let (out, summand) = tmp.split_at_mut(1);
+                            MixtureSumNode::new(&mut *out[0], &mut *summand[0], model.noise())
+                        }));
+                    }
+                }
+
+                s.push(skill);
+                p.push(player_perf);
+                team_sum.push(player_perf);
             }
-            pt.push(SumNode::new(&mut tt));
-            let mut tmp: Vec<&mut dyn ValueNode> = Vec::with_capacity(3);
-            tmp.push(&mut l[i]);
-            tmp.push(&mut t[i][j]);
-            tmp.push(&mut u[i][j]);
-            tul.push(SumNode::new(&mut tmp));
-            conv.push(t[i][j].get_edges().last().unwrap().clone());
+
+            let bias = info.bias(contest, i, j);
+            pt.push(graph.add_factor_with(&team_sum, |tmp| {
+                WeightedSumNode::new(tmp, vec![1.; tmp.len() - 1]).with_offset(bias)
+            }));
+            let diff = graph.add_variable(LeqNode::new(EPS));
+            tul.push(graph.add_factor::<SumNode>(&[l[i], team_perf, diff]));
+            conv.push(graph.edges(team_perf).last().unwrap().clone());
+            t.push(team_perf);
+            u.push(diff);
         }
 
         if i != 0 {
-            let mut tmp: Vec<&mut dyn ValueNode> = Vec::with_capacity(3);
-            let (a, b) = l.split_at_mut(i);
-            tmp.push(a.last_mut().unwrap());
-            tmp.push(b.first_mut().unwrap());
-            tmp.push(&mut d[i - 1]);
-            ld.push(SumNode::new(&mut tmp));
+            let diff = graph.add_variable(GreaterNode::new(2. * EPS));
+            ld.push(graph.add_factor::<SumNode>(&[l[i - 1], l[i], diff]));
+            d.push(diff);
         }
     }
 
-    infer3(&mut s);
-    infer1(&mut sp);
-    infer3(&mut p);
-    infer1(&mut pt);
-    infer2(&mut t);
-    infer1(&mut tul);
-    infer2(&mut u);
-    infer1(&mut tul);
+    let schedule = Schedule::Sequence(vec![
+        Schedule::Nodes([&c[..], &cs, &s, &sp, &p, &pt, &t, &tul, &u, &tul].concat()),
+        Schedule::Loop {
+            body: Box::new(Schedule::Sequence(vec![
+                ld_schedule(&ld, &l),
+                Schedule::Nodes(d),
+                ld_schedule(&ld, &l),
+                Schedule::Nodes([&tul[..], &u, &tul].concat()),
+            ])),
+            watch: conv,
+            eps: CONVERGENCE_EPS,
+            max_rounds: None,
+        },
+        Schedule::Nodes([&t[..], &pt, &p, &sp, &s, &cs, &c].concat()),
+    ]);
+
+    let rounds = graph.run(&schedule);
 
-    let mut rounds = 0;
+    eprintln!("Rounds until convergence: {}", rounds);
 
-    while check_convergence(&conv, &old_conv) >= CONVERGENCE_EPS {
-        old_conv.clear();
-        for item in &conv {
-            old_conv.push(RefCell::borrow(item).clone());
-        }
-        rounds += 1;
+    for (r, name, mess) in &players {
+        let prior = RefCell::borrow(&Weak::upgrade(mess).unwrap()).0.clone();
+        let performance = RefCell::borrow(&Weak::upgrade(mess).unwrap()).1.clone();
 
-        infer_ld(&mut ld, &mut l);
-        infer1(&mut d);
-        infer_ld(&mut ld, &mut l);
-        infer1(&mut tul);
-        infer2(&mut u);
-        infer1(&mut tul);
+        *ratings[*r].get_mut(name).unwrap() = PlayerRating::try_from(prior * performance)
+            .expect("Posterior of a player is improper");
     }
+}
+
+
+pub fn simulate_contest(rating_history: &mut RatingHistory, contest: &Contest, when: usize) {
+    simulate_contest_with(&Config::default(), rating_history, contest, &ContestInfo::default(), when);
+}
+
+
+pub fn simulate_contest_with(config: &Config, rating_history: &mut RatingHistory, contest: &Contest,
+                             info: &ContestInfo, when: usize) -> ContestReport {
+    simulate(config, &mut [(rating_history, DEFAULT_PLAYER_RATING)], contest, info, when)
+}
 
-    eprintln!("Rounds until convergence: {}", rounds);
 
-    infer2(&mut t);
-    infer1(&mut pt);
-    infer3(&mut p);
-    infer1(&mut sp);
-    infer3(&mut s);
+/// Same as `simulate_contest_with`, but updates both the general skill and the `category` component
+pub fn simulate_category_contest(config: &Config, rating_history: &mut CategoryRatingHistory,
+                                 contest: &Contest, info: &ContestInfo, category: &Category,
+                                 when: usize) -> ContestReport {
+    let components = rating_history.components.entry(category.clone()).or_default();
+
+    simulate(config, &mut [(&mut rating_history.general, DEFAULT_PLAYER_RATING),
+        (components, DEFAULT_CATEGORY_RATING)], contest, info, when)
+}
+
+
+/// Skill of every player is the sum of their ratings in `histories`, each with its default rating
+fn simulate(config: &Config, histories: &mut [(&mut RatingHistory, PlayerRating)], contest: &Contest,
+            info: &ContestInfo, when: usize) -> ContestReport {
+    let (contest, info, sigma_only) = apply_statuses(config, contest, info);
+    let sigma_only = vec![sigma_only.into_iter().map(|player| vec![player]).collect()];
+
+    // the prior provider seeds and the normalization shifts only the general skill, which always goes first
+    let prior = |r: usize| if r == 0 { config.prior.as_ref() } else { None };
+    let normalization = |r: usize| if r == 0 { config.normalization.as_ref() } else { None };
+
+    let mut ratings = Vec::with_capacity(histories.len());
+    for (r, (history, default)) in histories.iter().enumerate() {
+        ratings.push(Rating::new());
+        load_rating(history, ratings.last_mut().unwrap(), &contest, when, default, prior(r), normalization(r));
+    }
 
-    for (name, mess) in &players {
-        let prior;
-        let performance;
+    let priors = ratings[0].clone();
+    inference(config, &mut ratings, &contest, &info);
+    let guarded = config.guards.apply(&priors, &mut ratings[0], &info);
 
-        prior = RefCell::borrow(&Weak::upgrade(mess).unwrap()).0.clone();
-        performance = RefCell::borrow(&Weak::upgrade(mess).unwrap()).1.clone();
+    for (r, ((history, default), rating)) in histories.iter_mut().zip(&ratings).enumerate() {
+        update_rating(rating, history, &contest, &info, when);
 
-        *rating.get_mut(name).unwrap() = prior * performance;
+        let mut grown = Rating::new();
+        load_rating(history, &mut grown, &sigma_only, when, default, prior(r), normalization(r));
+        update_rating(&grown, history, &sigma_only, &info, when);
+    }
+
+    ContestReport {
+        population: config.normalization.as_ref()
+            .map(|normalization| normalize(histories[0].0, normalization, when)),
+        guarded,
     }
 }
 
 
-pub fn simulate_contest(rating_history: &mut RatingHistory, contest: &Contest, when: usize) {
-    let mut contest_rating = Rating::new();
-    load_rating(rating_history, &mut contest_rating, &contest, when);
+/// Shifts the ratings of the players active at `when` to keep their anchor at the target.
+/// The entries made at `when` are shifted in place, the shift of the others is recorded in `normalization`.
+pub fn normalize(rating_history: &mut RatingHistory, normalization: &Normalization, when: usize)
+                 -> PopulationStats {
+    let is_active = |history: &Vec<(PlayerRating, usize)>|
+        history.last().unwrap().1.saturating_add(normalization.active_period) >= when;
+
+    let mut active: Vec<f64> = rating_history.values()
+        .filter(|history| is_active(history))
+        .map(|history| normalization.current(history, when).mu)
+        .collect();
 
-    inference(&mut contest_rating, &contest);
+    if active.is_empty() {
+        return PopulationStats { when, active: 0, mean: f64::NAN, median: f64::NAN, shift: 0. };
+    }
+
+    active.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mean = active.iter().sum::<f64>() / active.len() as f64;
+    let median = (active[(active.len() - 1) / 2] + active[active.len() / 2]) / 2.;
+    let shift = normalization.target - match normalization.anchor {
+        Anchor::Mean => mean,
+        Anchor::Median => median,
+    };
+
+    for history in rating_history.values_mut().filter(|history| history.last().unwrap().1 == when) {
+        history.last_mut().unwrap().0.mu += shift;
+    }
+    let total = normalization.total_shift(when) + shift;
+    normalization.shifts.borrow_mut().push((when, total));
 
-    update_rating(&contest_rating, rating_history, &contest, when);
+    PopulationStats { when, active: active.len(), mean, median, shift }
+}
+
+
+fn sort_leaderboard(entries: &mut [LeaderboardEntry]) {
+    entries.sort_by(|a, b| b.rating.mu.partial_cmp(&a.rating.mu).unwrap());
+}
+
+
+/// Players sorted by their latest rating
+pub fn leaderboard(rating_history: &RatingHistory, provisional: &Provisional) -> Vec<LeaderboardEntry> {
+    let mut ans: Vec<LeaderboardEntry> = rating_history.iter()
+        .map(|(player, history)| LeaderboardEntry {
+            player: player.clone(),
+            rating: history.last().unwrap().0.clone(),
+            contests: history.len(),
+            provisional: provisional.is_provisional(history),
+        })
+        .collect();
+
+    sort_leaderboard(&mut ans);
+
+    ans
+}
+
+
+/// Players sorted by their latest skill in `category` (general skill plus the component).
+/// Provisional status is determined by the general skill.
+pub fn category_leaderboard(rating_history: &CategoryRatingHistory, category: &Category,
+                            provisional: &Provisional) -> Vec<LeaderboardEntry> {
+    let components = rating_history.components.get(category);
+    let mut ans = leaderboard(&rating_history.general, provisional);
+
+    for entry in &mut ans {
+        let component = components.and_then(|c| c.get(&entry.player))
+            .map_or(DEFAULT_CATEGORY_RATING, |c| c.last().unwrap().0.clone());
+        entry.rating = &entry.rating + component;
+    }
+
+    sort_leaderboard(&mut ans);
+
+    ans
 }
 
 
@@ -287,7 +787,7 @@ mod tests {
                 to_sort.push((player.parse::<i32>().unwrap(), posterior.last().unwrap().0.clone()));
             }
 
-            to_sort.sort_by(|(ak, _av), (bk, _bv)| ak.cmp(bk));
+            to_sort.sort_by_key(|(k, _v)| *k);
             let to_sort = to_sort;
 
             assert!(approx_eq!(f64, to_sort[mid].1.mu, MU, epsilon = 2. * CONVERGENCE_EPS),
@@ -308,4 +808,304 @@ mod tests {
             one_contest(*mid);
         }
     }
+
+    fn upset_loss(config: &Config) -> f64 {
+        let mut rating = RatingHistory::new();
+        rating.insert("strong".to_string(), vec![(PlayerRating { mu: 2500., sigma: 100. }, 0)]);
+        rating.insert("weak".to_string(), vec![(PlayerRating { mu: 1000., sigma: 100. }, 0)]);
+
+        let contest = vec![vec![vec!["weak".to_string()]], vec![vec!["strong".to_string()]]];
+        simulate_contest_with(config, &mut rating, &contest, &ContestInfo::default(), 0);
+
+        2500. - rating["strong"].last().unwrap().0.mu
+    }
+
+    #[test]
+    fn mixture_limits_outliers() {
+        let gaussian = upset_loss(&Config::default());
+        let mixture = upset_loss(&Config {
+            performance: PerformanceModel::Mixture { outlier_probability: 0.05, outlier_scale: 10. },
+            ..Default::default()
+        });
+
+        assert!(mixture > 0., "Loss must still lower the rating: {}", mixture);
+        assert!(mixture < gaussian / 2., "Outlier is not down-weighted: {} vs {}", mixture, gaussian);
+    }
+
+    #[test]
+    fn mixture_monotonicity() {
+        let config = Config {
+            performance: PerformanceModel::Mixture { outlier_probability: 0.05, outlier_scale: 10. },
+            ..Default::default()
+        };
+
+        let mut rating = RatingHistory::new();
+        let contest: Contest = (0..20).map(|i| vec![vec![i.to_string()]]).collect();
+
+        simulate_contest_with(&config, &mut rating, &contest, &ContestInfo::default(), 0);
+
+        for i in 1..contest.len() {
+            let prev = &rating[&(i - 1).to_string()].last().unwrap().0;
+            let curr = &rating[&i.to_string()].last().unwrap().0;
+            assert!(prev.mu > curr.mu - CONVERGENCE_EPS, "Fail at i = {}: {} <= {}", i, prev.mu, curr.mu);
+        }
+    }
+
+    #[test]
+    fn category_leaderboards() {
+        let mut rating = CategoryRatingHistory::default();
+        let math = "math".to_string();
+        let implementation = "implementation".to_string();
+
+        for when in 0..5 {
+            let a_wins = vec![vec![vec!["a".to_string()]], vec![vec!["b".to_string()]]];
+            let b_wins = vec![vec![vec!["b".to_string()]], vec![vec!["a".to_string()]]];
+            simulate_category_contest(&Config::default(), &mut rating, &a_wins, &ContestInfo::default(),
+                                      &math, 2 * when);
+            simulate_category_contest(&Config::default(), &mut rating, &b_wins, &ContestInfo::default(),
+                                      &implementation, 2 * when + 1);
+        }
+
+        let math_leaders = category_leaderboard(&rating, &math, &Provisional::default());
+        let implementation_leaders = category_leaderboard(&rating, &implementation, &Provisional::default());
+
+        assert_eq!(math_leaders[0].player, "a");
+        assert_eq!(implementation_leaders[0].player, "b");
+        assert!(math_leaders[0].rating.sigma > rating.general["a"].last().unwrap().0.sigma);
+
+        let general_a = rating.general["a"].last().unwrap().0.mu;
+        let general_b = rating.general["b"].last().unwrap().0.mu;
+        assert!(f64::abs(general_a - general_b) < f64::abs(math_leaders[0].rating.mu - math_leaders[1].rating.mu));
+    }
+
+    #[test]
+    fn handicap() {
+        fn winner_gain(info: &ContestInfo) -> f64 {
+            let mut rating = RatingHistory::new();
+            let contest = vec![vec![vec!["a".to_string()]], vec![vec!["b".to_string()]]];
+            simulate_contest_with(&Config::default(), &mut rating, &contest, info, 0);
+
+            rating["a"].last().unwrap().0.mu - MU
+        }
+
+        let fair = winner_gain(&ContestInfo::default());
+        let handicapped = winner_gain(&ContestInfo { team_bias: vec![vec![], vec![-500.]], ..Default::default() });
+        let helped = winner_gain(&ContestInfo {
+            player_bias: vec![("a".to_string(), 500.)].into_iter().collect(),
+            ..Default::default()
+        });
+
+        assert!(handicapped < fair, "{} >= {}", handicapped, fair);
+        assert!(helped < fair, "{} >= {}", helped, fair);
+        assert!(approx_eq!(f64, handicapped, helped, epsilon = 1e-6));
+    }
+
+    #[test]
+    fn frozen() {
+        let mut rating = RatingHistory::new();
+        rating.insert("ghost".to_string(), vec![(PlayerRating { mu: 2000., sigma: 100. }, 0)]);
+
+        let contest = vec![vec![vec!["a".to_string()]], vec![vec!["ghost".to_string()]],
+                           vec![vec!["new".to_string()]]];
+        let info = ContestInfo {
+            frozen: vec!["ghost".to_string(), "new".to_string()].into_iter().collect(),
+            ..Default::default()
+        };
+        simulate_contest_with(&Config::default(), &mut rating, &contest, &info, 10);
+
+        assert_eq!(rating["ghost"].len(), 1);
+        assert!(!rating.contains_key("new"));
+        // beating a strong frozen player still counts
+        assert!(rating["a"].last().unwrap().0.mu > 2000.);
+    }
+
+    #[test]
+    fn statuses() {
+        let contest = vec![vec![vec!["a".to_string()]], vec![vec!["cheater".to_string()]],
+                           vec![vec!["absent".to_string()]], vec![vec!["b".to_string()]]];
+        let info = ContestInfo {
+            status: vec![("cheater".to_string(), Status::Disqualified), ("absent".to_string(), Status::NoShow)]
+                .into_iter().collect(),
+            ..Default::default()
+        };
+
+        let mut rating = RatingHistory::new();
+        rating.insert("absent".to_string(), vec![(DEFAULT_PLAYER_RATING, 0)]);
+        simulate_contest_with(&Config::default(), &mut rating, &contest, &info, 0);
+
+        assert_eq!(rating["absent"].len(), 1);
+        assert!(rating["cheater"].last().unwrap().0.mu < rating["b"].last().unwrap().0.mu);
+
+        let config = Config {
+            no_show: StatusPolicy::SigmaOnly,
+            disqualified: StatusPolicy::Ignore,
+            ..Default::default()
+        };
+        let mut rating = RatingHistory::new();
+        rating.insert("absent".to_string(), vec![(PlayerRating { mu: MU, sigma: 10. }, 0)]);
+        simulate_contest_with(&config, &mut rating, &contest, &info, 10000);
+
+        assert!(!rating.contains_key("cheater"));
+        assert_eq!(rating["absent"].len(), 2);
+        assert_eq!(rating["absent"][1].0.mu, MU);
+        assert!(rating["absent"][1].0.sigma > 10.);
+        assert!(approx_eq!(f64, rating["a"].last().unwrap().0.mu - MU, MU - rating["b"].last().unwrap().0.mu,
+                           epsilon = 1e-6));
+    }
+
+    #[test]
+    fn seeded_prior() {
+        // Codeforces reports 0 for newcomers
+        let known: HashMap<Player, i32> = vec![("tourist".to_string(), 3500), ("newbie".to_string(), 0)]
+            .into_iter().collect();
+        let config = Config {
+            prior: Some(PriorProvider::new(move |player| {
+                known.get(player).and_then(|&rating| codeforces_prior(rating, 0))
+            })),
+            ..Default::default()
+        };
+
+        let mut rating = RatingHistory::new();
+        let contest = vec![vec![vec!["newbie".to_string()]], vec![vec!["tourist".to_string()]]];
+        simulate_contest_with(&config, &mut rating, &contest, &ContestInfo::default(), 0);
+
+        assert!(rating["tourist"].last().unwrap().0.mu > 3000.);
+        assert!(rating["tourist"].last().unwrap().0.mu > rating["newbie"].last().unwrap().0.mu);
+        assert!(rating["newbie"].last().unwrap().0.mu > MU);
+        assert!(codeforces_prior(0, 0).is_none());
+    }
+
+    #[test]
+    fn codeforces_newcomers() {
+        let before = CF_DEFLATION_START - 1;
+
+        assert!(codeforces_prior(1500, before).is_none());
+        assert!(codeforces_prior(0, before).is_none());
+        assert_eq!(codeforces_prior(1600, before).unwrap().mu, 1600.);
+        assert!(codeforces_prior(1600, before).unwrap().sigma < SIGMA);
+
+        // a deflated rating is no more confident than no rating at all
+        let deflated = codeforces_prior(1500, CF_DEFLATION_START).unwrap();
+        assert!(deflated.mu > 1500.);
+        assert_eq!(deflated.sigma, SIGMA);
+    }
+
+    #[test]
+    fn normalization() {
+        let config = Config {
+            prior: Some(PriorProvider::new(|player| if player == "strong" {
+                Some(PlayerRating { mu: 3000., sigma: 100. })
+            } else {
+                None
+            })),
+            normalization: Some(Normalization::new(Anchor::Mean, MU, 100)),
+            ..Default::default()
+        };
+
+        let mut rating = RatingHistory::new();
+        rating.insert("retired".to_string(), vec![(PlayerRating { mu: 0., sigma: 100. }, 0)]);
+
+        let contest = vec![vec![vec!["strong".to_string()]], vec![vec!["weak".to_string()]]];
+        let report = simulate_contest_with(&config, &mut rating, &contest, &ContestInfo::default(), 1000);
+        let population = report.population.unwrap();
+
+        assert_eq!(population.active, 2);
+        assert!(population.mean > MU);
+        assert!(approx_eq!(f64, population.shift, MU - population.mean));
+        assert!(approx_eq!(f64, population.mean, population.median));
+        assert_eq!(rating["retired"][0].0.mu, 0.);
+
+        let mean = (rating["strong"].last().unwrap().0.mu + rating["weak"].last().unwrap().0.mu) / 2.;
+        assert!(approx_eq!(f64, mean, MU, epsilon = 1e-9));
+    }
+
+    #[test]
+    fn normalization_keeps_history() {
+        let config = Config {
+            normalization: Some(Normalization::new(Anchor::Mean, MU, 100)),
+            ..Default::default()
+        };
+        let normalization = config.normalization.as_ref().unwrap();
+
+        let mut rating = RatingHistory::new();
+        rating.insert("idle".to_string(), vec![(PlayerRating { mu: 2100., sigma: 100. }, 950)]);
+
+        let contest = vec![vec![vec!["a".to_string()]], vec![vec!["b".to_string()]]];
+        let shift = simulate_contest_with(&config, &mut rating, &contest, &ContestInfo::default(), 1000)
+            .population.unwrap().shift;
+        assert!(approx_eq!(f64, shift, -200., epsilon = 1e-9));
+
+        // the entry computed before the contest stays, the shift is pending
+        assert_eq!(rating["idle"].len(), 1);
+        assert_eq!((rating["idle"][0].0.mu, rating["idle"][0].1), (2100., 950));
+        assert!(approx_eq!(f64, normalization.current(&rating["idle"], 1000).mu, 1900.));
+        assert_eq!(normalization.leaderboard(&rating, &Provisional::default(), 1000)[0].player, "idle");
+        assert!(approx_eq!(f64, normalization.leaderboard(&rating, &Provisional::default(), 1000)[0].rating.mu, 1900.));
+
+        // and is applied when the player returns
+        let contest = vec![vec![vec!["idle".to_string()]], vec![vec!["a".to_string()]]];
+        simulate_contest_with(&config, &mut rating, &contest, &ContestInfo::default(), 1010);
+        assert_eq!(rating["idle"].len(), 2);
+        assert!(rating["idle"][1].0.mu < 2000.);
+        assert_eq!(normalization.pending_shift(&rating["idle"], 1010), 0.);
+    }
+
+    #[test]
+    fn normalization_never_expires() {
+        let mut rating = RatingHistory::new();
+        rating.insert("a".to_string(), vec![(PlayerRating { mu: 1000., sigma: 100. }, 5)]);
+        rating.insert("b".to_string(), vec![(PlayerRating { mu: 1200., sigma: 100. }, 5)]);
+
+        let normalization = Normalization::new(Anchor::Median, MU, usize::MAX);
+        let population = normalize(&mut rating, &normalization, 10);
+
+        assert_eq!(population.active, 2);
+        assert_eq!(rating["a"][0].0.mu, 1000.);
+        assert!(approx_eq!(f64, normalization.current(&rating["a"], 10).mu, 1400.));
+        assert_eq!(normalization.pending_shift(&rating["a"], 4), 0.);
+    }
+
+    #[test]
+    fn guards() {
+        let config = Config {
+            guards: Guards { min_sigma: Some(95.), max_mu_change: Some(50.), max_sigma_reduction: Some(3.) },
+            ..Default::default()
+        };
+
+        let mut rating = RatingHistory::new();
+        rating.insert("veteran".to_string(), vec![(PlayerRating { mu: 2000., sigma: 100. }, 0)]);
+
+        let contest = vec![vec![vec!["newbie".to_string()]], vec![vec!["veteran".to_string()]]];
+        let report = simulate_contest_with(&config, &mut rating, &contest, &ContestInfo::default(), 0);
+
+        assert_eq!(report.guarded[0].player, "newbie");
+        assert_eq!(report.guarded[0].reasons, vec![GuardReason::MaxMuChange, GuardReason::MaxSigmaReduction]);
+        assert!(report.guarded[0].computed.mu > MU + 50.);
+
+        let newbie = &rating["newbie"].last().unwrap().0;
+        assert!(approx_eq!(f64, newbie.mu, MU + 50.));
+        assert!(approx_eq!(f64, newbie.sigma, SIGMA - 3.));
+
+        let veteran = &rating["veteran"].last().unwrap().0;
+        assert!(veteran.sigma >= 97. - 1e-9);
+        assert!(veteran.mu >= 1950. - 1e-9);
+    }
+
+    #[test]
+    fn provisional() {
+        let mut rating = RatingHistory::new();
+        for when in 0..3 {
+            let contest = vec![vec![vec!["regular".to_string()]], vec![vec![format!("guest{}", when)]]];
+            simulate_contest(&mut rating, &contest, when);
+        }
+
+        let board = leaderboard(&rating, &Provisional { min_contests: 3, max_sigma: None });
+        assert_eq!(board[0].player, "regular");
+        assert_eq!(board[0].contests, 3);
+        assert!(!board[0].provisional);
+        assert!(board[1..].iter().all(|entry| entry.provisional));
+
+        let board = leaderboard(&rating, &Provisional { min_contests: 0, max_sigma: Some(1.) });
+        assert!(board.iter().all(|entry| entry.provisional));
+    }
 }
@@ -1,101 +1,154 @@
-use trueskill_spb::{Contest, simulate_contest};
-use read_codeforces::{get_contest, get_contest_ids};
-use read_codeforces::Contest as EbTechContest;
+use trueskill_spb::{Config, Provisional, leaderboard, simulate_contest_with};
+use trueskill_spb::source::{Aliases, AliasedSource, CodeforcesSource, ContestSource, FileSource};
+use read_codeforces::{Client, get_contest_ids, write_contest_ids};
 use std::time;
 
 
-fn contest_adaptor(from: &EbTechContest) -> (Contest, usize) {
-    let mut ans = Contest::new();
+fn simulate_stored_contests(rating: &mut trueskill_spb::RatingHistory, source: &dyn ContestSource) {
+    let config = Config::default();
 
-    for i in 1..from.standings.len() {
-        assert!(from.standings[i - 1].1 <= from.standings[i].1);
-    }
-
-    let mut prev = usize::MAX;
-
-    for (user, lo, _hi) in &from.standings {
-        if *lo != prev {
-            ans.push(Vec::new());
+    for record in source.contests() {
+        let record = record.expect("Failed to read a contest");
+        for issue in &record.issues {
+            eprintln!("WARNING @ {}: {}", record.id, issue);
         }
-        ans.last_mut().unwrap().push(vec![user.clone()]);
-
-        prev = *lo;
-    }
-
-    (ans, from.time_seconds)
-}
-
-
-fn simulate_stored_contests(rating: &mut trueskill_spb::RatingHistory) {
-    for contest_id in get_contest_ids() {
-        let contest: EbTechContest = get_contest("cache", contest_id);
         println!(
             "Processing {:5} contestants in contest/{:4}: {}",
-            contest.standings.len(),
-            contest.id,
-            contest.name
+            record.contest.iter().flatten().map(Vec::len).sum::<usize>(),
+            record.id,
+            record.name
         );
-        let adapted = contest_adaptor(&contest);
-        simulate_contest(rating, &adapted.0, adapted.1);
+        simulate_contest_with(&config, rating, &record.contest, &record.info, record.when);
     }
 }
 
 
-fn write_results(rating: &trueskill_spb::RatingHistory, filename: &str, history_size: usize) {
+/// How to output players with provisional ratings
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ProvisionalOutput {
+    Mark,
+    Hide,
+}
+
+
+fn write_results(rating: &trueskill_spb::RatingHistory, filename: &str, history_size: usize,
+                 provisional: ProvisionalOutput) {
     use std::io::Write;
     let file = std::fs::File::create(filename).expect("Output file not found");
     let mut out = std::io::BufWriter::new(file);
-    let mut to_sort = Vec::new();
-
-    for (key, value) in rating {
-        to_sort.push((key.clone(), value.clone()));
-    }
 
-    to_sort.sort_by(|(_ak, av), (_bk, bv)|
-        av.last().unwrap().0.mu.partial_cmp(&bv.last().unwrap().0.mu).unwrap());
-    to_sort.reverse();
+    let entries = leaderboard(rating, &Provisional::default()).into_iter()
+        .filter(|entry| provisional == ProvisionalOutput::Mark || !entry.provisional);
 
-    let mut ord = 1;
-    for (key, value) in to_sort {
-        write!(out, "{}.\t{:30}", ord, key).ok();
-        ord += 1;
+    for (ord, entry) in entries.enumerate() {
+        let value = &rating[&entry.player];
+        write!(out, "{}.\t{:30}", ord + 1, entry.player).ok();
         for (rating, _when) in &value[value.len() - usize::min(history_size, value.len())..value.len()] {
             write!(out, "\t({:.2}, {:.2})", rating.mu, rating.sigma).ok();
         }
+        if entry.provisional {
+            write!(out, "\tprovisional").ok();
+        }
         writeln!(out).ok();
     }
 }
 
 
+/// Appends new rated Codeforces contests to the id list
+fn update_contest_ids() {
+    let client = Client::new().expect("Failed to create an HTTP client");
+    let known = get_contest_ids();
+    let update = client.update_contest_ids("cache", &known).unwrap_or_else(|err| panic!("{}", err));
+
+    for id in &update.new {
+        println!("New contest/{}", id);
+    }
+    for (id, err) in &update.failed {
+        eprintln!("Failed to fetch contest/{}: {}", id, err);
+    }
+    write_contest_ids(&update.ids).expect("Failed to write contest IDs");
+}
+
+
+const ALIASES_FILE: &str = "data/aliases.txt";
+
+
+/// Adds renames of all the participants of the Codeforces contests to the alias file
+fn update_aliases() {
+    let client = Client::new().expect("Failed to create an HTTP client");
+    let source = CodeforcesSource::new("cache", get_contest_ids(), client);
+
+    let mut players = std::collections::BTreeSet::new();
+    for record in source.contests() {
+        let record = record.expect("Failed to read a contest");
+        players.extend(record.contest.into_iter().flatten().flatten());
+    }
+    let players: Vec<_> = players.into_iter().collect();
+
+    let mut aliases = if std::path::Path::new(ALIASES_FILE).exists() {
+        Aliases::load(ALIASES_FILE).expect("Failed to read aliases")
+    } else {
+        Aliases::new()
+    };
+    let fetched = Aliases::fetch(&source.client, &players).expect("Failed to fetch current handles");
+    println!("Found {} renamed handles", fetched.len());
+
+    aliases.extend(fetched);
+    aliases.save(ALIASES_FILE).expect("Failed to write aliases");
+}
+
+
 fn main() {
+    match std::env::args().nth(1).as_deref() {
+        Some("update-ids") => return update_contest_ids(),
+        Some("update-aliases") => return update_aliases(),
+        _ => {}
+    }
+
     let mut rating = trueskill_spb::RatingHistory::new();
 
     let now = time::Instant::now();
 
-    simulate_stored_contests(&mut rating);
+    // a CSV or JSON lines file with contests replaces Codeforces history
+    let source: Box<dyn ContestSource> = match std::env::args().nth(1) {
+        Some(path) => Box::new(FileSource::new(path)),
+        None => {
+            let client = Client::new().expect("Failed to create an HTTP client");
+            Box::new(CodeforcesSource::new("cache", get_contest_ids(), client))
+        }
+    };
+
+    // histories of renamed players are merged under their current handles
+    let source: Box<dyn ContestSource> = if std::path::Path::new(ALIASES_FILE).exists() {
+        let aliases = Aliases::load(ALIASES_FILE).expect("Failed to read aliases");
+        Box::new(AliasedSource { source, aliases })
+    } else {
+        source
+    };
+
+    simulate_stored_contests(&mut rating, source.as_ref());
 
     let rating = rating;
     let mut actual_rating = trueskill_spb::RatingHistory::new();
 
     for (key, value) in &rating {
-        if value.last().unwrap().1 >= 1578148500 &&  // "Hello 2020"
-            value.len() >= 10 {
+        if value.last().unwrap().1 >= 1578148500 {  // "Hello 2020"
             actual_rating.insert(key.clone(), value.clone());
         }
     }
 
     let actual_rating = actual_rating;
 
-    write_results(&rating, "data/CFratings.txt", 1);
-    write_results(&rating, "data/CFratings_10.txt", 10);
-    write_results(&rating, "data/CFratings_full.txt", usize::MAX);
-    write_results(&actual_rating, "data/CFratings_actual.txt", 1);
-    write_results(&actual_rating, "data/CFratings_10_actual.txt", 10);
-    write_results(&actual_rating, "data/CFratings_full_actual.txt", usize::MAX);
+    write_results(&rating, "data/CFratings.txt", 1, ProvisionalOutput::Mark);
+    write_results(&rating, "data/CFratings_10.txt", 10, ProvisionalOutput::Mark);
+    write_results(&rating, "data/CFratings_full.txt", usize::MAX, ProvisionalOutput::Mark);
+    write_results(&actual_rating, "data/CFratings_actual.txt", 1, ProvisionalOutput::Hide);
+    write_results(&actual_rating, "data/CFratings_10_actual.txt", 10, ProvisionalOutput::Hide);
+    write_results(&actual_rating, "data/CFratings_full_actual.txt", usize::MAX, ProvisionalOutput::Hide);
 
     let mut sum = 0.;
 
-    for (_key, val) in &rating {
+    for val in rating.values() {
         sum += val.last().unwrap().0.mu;
     }
 
@@ -1,25 +1,34 @@
 extern crate distributions;
 
 use distributions::normal::Gaussian;
-use distributions::normal::{ZERO, ONE};
+use distributions::normal::natural::GaussianNat;
+use distributions::normal::natural::{ZERO, ONE};
 use std::rc::{Rc, Weak};
 use std::cell::RefCell;
+use std::convert::TryFrom;
 
-pub type Message = Gaussian;
+/// Every edge holds `(factor -> variable, variable -> factor)` messages
+pub type Message = GaussianNat;
 
+/// Any node of a factor graph, `infer` recomputes its outgoing messages
 pub trait TreeNode {
     fn infer(&mut self);
 }
 
+/// Variable node
 pub trait ValueNode: TreeNode {
+    /// Creates a new edge owned by the variable
     fn add_edge(&mut self) -> Weak<RefCell<(Message, Message)>>;
+    /// All edges in the order of creation
+    fn edges(&self) -> Vec<Weak<RefCell<(Message, Message)>>>;
 }
 
+/// Factor node which is fully defined by its neighbours
 pub trait FuncNode: TreeNode {
     fn new(neighbours: &mut [&mut dyn ValueNode]) -> Self;
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct ProdNode {
     edges: Vec<Rc<RefCell<(Message, Message)>>>
 }
@@ -42,13 +51,37 @@ pub struct SumNode {
     sum_edges: Vec<Weak<RefCell<(Message, Message)>>>,
 }
 
+/// `out = Σ coefficients[i] * in[i] + offset` for arbitrary real coefficients
+#[derive(Clone)]
+pub struct WeightedSumNode {
+    out_edge: Weak<RefCell<(Message, Message)>>,
+    sum_edges: Vec<Weak<RefCell<(Message, Message)>>>,
+    coefficients: Vec<f64>,
+    offset: f64,
+}
+
+/// `out = in + noise`, where noise is a mixture of Gaussians given as `(weight, distribution)`.
+/// The factor is not Gaussian, so the messages are moment-matched (expectation propagation).
+#[derive(Clone)]
+pub struct MixtureSumNode {
+    out_edge: Weak<RefCell<(Message, Message)>>,
+    in_edge: Weak<RefCell<(Message, Message)>>,
+    noise: Vec<(f64, Gaussian)>,
+}
+
+impl<T: TreeNode + ?Sized> TreeNode for Box<T> {
+    fn infer(&mut self) {
+        (**self).infer();
+    }
+}
+
 impl TreeNode for ProdNode {
     fn infer(&mut self) {
         fn get_prefix_prods(from: &[Rc<RefCell<(Message, Message)>>]) -> Vec<Message> {
             let mut prefix_prods = vec![ONE; from.len() + 1];
 
             for i in 1..prefix_prods.len() {
-                prefix_prods[i] = &prefix_prods[i - 1] * &RefCell::borrow(&&from[i - 1]).0;
+                prefix_prods[i] = &prefix_prods[i - 1] * &RefCell::borrow(&from[i - 1]).0;
             }
 
             prefix_prods
@@ -69,7 +102,11 @@ impl TreeNode for ProdNode {
 impl ValueNode for ProdNode {
     fn add_edge(&mut self) -> Weak<RefCell<(Message, Message)>> {
         self.edges.push(Rc::new(RefCell::new((ONE, ZERO))));
-        Rc::downgrade(&self.edges.last().unwrap())
+        Rc::downgrade(self.edges.last().unwrap())
+    }
+
+    fn edges(&self) -> Vec<Weak<RefCell<(Message, Message)>>> {
+        self.edges.iter().map(Rc::downgrade).collect()
     }
 }
 
@@ -89,11 +126,11 @@ impl ProdNode {
 
 impl TreeNode for LeqNode {
     fn infer(&mut self) {
-        let ans;
-        {
-            ans = RefCell::borrow(&self.edge).0.leq_eps(self.eps);
+        // improper cavity has no truncated moments, keep the previous message then
+        let ans = RefCell::borrow(&self.edge).0.leq_eps(self.eps);
+        if let Some(ans) = ans {
+            RefCell::borrow_mut(&self.edge).1 = ans;
         }
-        RefCell::borrow_mut(&self.edge).1 = ans;
     }
 }
 
@@ -101,6 +138,10 @@ impl ValueNode for LeqNode {
     fn add_edge(&mut self) -> Weak<RefCell<(Message, Message)>> {
         Rc::downgrade(&self.edge)
     }
+
+    fn edges(&self) -> Vec<Weak<RefCell<(Message, Message)>>> {
+        vec![Rc::downgrade(&self.edge)]
+    }
 }
 
 impl LeqNode {
@@ -111,11 +152,10 @@ impl LeqNode {
 
 impl TreeNode for GreaterNode {
     fn infer(&mut self) {
-        let ans;
-        {
-            ans = RefCell::borrow(&self.edge).0.greater_eps(self.eps);
+        let ans = RefCell::borrow(&self.edge).0.greater_eps(self.eps);
+        if let Some(ans) = ans {
+            RefCell::borrow_mut(&self.edge).1 = ans;
         }
-        RefCell::borrow_mut(&self.edge).1 = ans;
     }
 }
 
@@ -123,6 +163,10 @@ impl ValueNode for GreaterNode {
     fn add_edge(&mut self) -> Weak<RefCell<(Message, Message)>> {
         Rc::downgrade(&self.edge)
     }
+
+    fn edges(&self) -> Vec<Weak<RefCell<(Message, Message)>>> {
+        vec![Rc::downgrade(&self.edge)]
+    }
 }
 
 impl GreaterNode {
@@ -133,11 +177,11 @@ impl GreaterNode {
 
 impl FuncNode for SumNode {
     fn new(neighbours: &mut [&mut dyn ValueNode]) -> Self {
-        assert!(neighbours.len() >= 1);
+        assert!(!neighbours.is_empty());
 
         let mut sum_edges = Vec::with_capacity(neighbours.len() - 1);
-        for i in 1..neighbours.len() {
-            sum_edges.push(neighbours[i].add_edge());
+        for neighbour in neighbours.iter_mut().skip(1) {
+            sum_edges.push(neighbour.add_edge());
         }
 
         SumNode {
@@ -176,3 +220,297 @@ impl TreeNode for SumNode {
         }
     }
 }
+
+impl WeightedSumNode {
+    /// The first neighbour is `out`, the rest are summands with the corresponding coefficients
+    pub fn new(neighbours: &mut [&mut dyn ValueNode], coefficients: Vec<f64>) -> Self {
+        assert_eq!(neighbours.len(), coefficients.len() + 1);
+
+        // until a neighbour sends anything, treat it as unknown rather than zero
+        let mut sum_edges = Vec::with_capacity(coefficients.len());
+        for neighbour in neighbours.iter_mut().skip(1) {
+            let edge = neighbour.add_edge();
+            RefCell::borrow_mut(&edge.upgrade().unwrap()).1 = ONE;
+            sum_edges.push(edge);
+        }
+
+        let out_edge = neighbours.first_mut().unwrap().add_edge();
+        RefCell::borrow_mut(&out_edge.upgrade().unwrap()).1 = ONE;
+
+        WeightedSumNode {
+            out_edge,
+            sum_edges,
+            coefficients,
+            offset: 0.,
+        }
+    }
+
+    /// Adds a known constant term to the sum
+    pub fn with_offset(mut self, offset: f64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Message of the i-th summand scaled by its coefficient
+    fn term(&self, i: usize) -> Message {
+        &RefCell::borrow(&self.sum_edges[i].upgrade().unwrap()).1 * self.coefficients[i]
+    }
+}
+
+impl TreeNode for WeightedSumNode {
+    fn infer(&mut self) {
+        let terms: Vec<Message> = (0..self.sum_edges.len()).map(|i| self.term(i)).collect();
+
+        let mut prefix_sums = vec![ZERO; terms.len() + 1];
+        for i in 0..terms.len() {
+            prefix_sums[i + 1] = &prefix_sums[i] + &terms[i];
+        }
+        let mut suffix_sums = vec![ZERO; terms.len() + 1];
+        for i in (0..terms.len()).rev() {
+            suffix_sums[i] = &suffix_sums[i + 1] + &terms[i];
+        }
+
+        RefCell::borrow_mut(&self.out_edge.upgrade().unwrap()).0 = prefix_sums.last().unwrap().shift(self.offset);
+
+        for i in 0..self.sum_edges.len() {
+            // a summand with zero coefficient does not affect the sum, hence learns nothing
+            let message = if self.coefficients[i] == 0. {
+                ONE
+            } else {
+                (&RefCell::borrow(&self.out_edge.upgrade().unwrap()).1.shift(-self.offset) -
+                    &prefix_sums[i] - &suffix_sums[i + 1]) / self.coefficients[i]
+            };
+
+            RefCell::borrow_mut(&self.sum_edges[i].upgrade().unwrap()).0 = message;
+        }
+    }
+}
+
+impl MixtureSumNode {
+    /// Panics unless the weights are in `[0, 1]` summing to 1 and every sigma is positive
+    pub fn new(out: &mut dyn ValueNode, summand: &mut dyn ValueNode, noise: Vec<(f64, Gaussian)>) -> Self {
+        assert!(!noise.is_empty());
+        for (weight, component) in &noise {
+            assert!((0. ..=1.).contains(weight), "Mixture weight {} is not in [0, 1]", weight);
+            assert!(component.sigma > 0., "Mixture component {:?} has non-positive sigma", component);
+        }
+        let total: f64 = noise.iter().map(|(weight, _)| weight).sum();
+        assert!((total - 1.).abs() < 1e-9, "Mixture weights sum to {}", total);
+
+        let out_edge = out.add_edge();
+        let in_edge = summand.add_edge();
+
+        // until the variables send anything, treat them as unknown rather than zero
+        RefCell::borrow_mut(&out_edge.upgrade().unwrap()).1 = ONE;
+        RefCell::borrow_mut(&in_edge.upgrade().unwrap()).1 = ONE;
+
+        MixtureSumNode { out_edge, in_edge, noise }
+    }
+
+    /// Message to the variable with the given `cavity`, when the other one is `other + sign * noise`
+    fn message(&self, cavity: &Message, other: &Message, sign: f64) -> Option<Message> {
+        if other.is_uniform() {
+            return Some(ONE);
+        }
+
+        let cavity_gaussian = if cavity.is_uniform() {
+            None
+        } else {
+            Some(Gaussian::try_from(cavity).ok()?)
+        };
+
+        let mut components = Vec::with_capacity(self.noise.len());
+
+        for (weight, noise) in &self.noise {
+            let likelihood = Gaussian::try_from(other + GaussianNat::from(noise * sign)).ok()?;
+
+            components.push(match &cavity_gaussian {
+                None => (weight.ln(), likelihood),
+                Some(cavity) => (weight.ln() + (cavity - &likelihood).log_pdf(0.), cavity * likelihood),
+            });
+        }
+
+        let max_log_weight = components.iter().map(|(w, _)| *w).fold(f64::NEG_INFINITY, f64::max);
+        let total: f64 = components.iter().map(|(w, _)| (w - max_log_weight).exp()).sum();
+
+        let mut mu = 0.;
+        for (log_weight, component) in &components {
+            mu += (log_weight - max_log_weight).exp() / total * component.mu;
+        }
+
+        // sigma^2 is twice the variance, hence the spread of the means counts twice
+        let mut sigma2 = 0.;
+        for (log_weight, component) in &components {
+            sigma2 += (log_weight - max_log_weight).exp() / total *
+                (component.sigma.powi(2) + 2. * (component.mu - mu).powi(2));
+        }
+
+        Some(GaussianNat::from(Gaussian { mu, sigma: sigma2.sqrt() }) / cavity)
+    }
+}
+
+impl TreeNode for MixtureSumNode {
+    fn infer(&mut self) {
+        let out_edge = self.out_edge.upgrade().unwrap();
+        let in_edge = self.in_edge.upgrade().unwrap();
+
+        let to_out = self.message(&RefCell::borrow(&out_edge).1, &RefCell::borrow(&in_edge).1, 1.);
+        let to_in = self.message(&RefCell::borrow(&in_edge).1, &RefCell::borrow(&out_edge).1, -1.);
+
+        // keep the previous messages if moment matching is impossible due to improper ones
+        if let Some(to_out) = to_out {
+            RefCell::borrow_mut(&out_edge).0 = to_out;
+        }
+        if let Some(to_in) = to_in {
+            RefCell::borrow_mut(&in_edge).0 = to_in;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable_message(node: &ProdNode) -> Gaussian {
+        Gaussian::try_from(&RefCell::borrow(node.get_edges().last().unwrap()).0).unwrap()
+    }
+
+    fn set_variable_message(node: &mut ProdNode, gaussian: Gaussian) {
+        RefCell::borrow_mut(node.get_edges_mut().last_mut().unwrap()).1 = GaussianNat::from(gaussian);
+    }
+
+    #[test]
+    fn weighted_sum_forward() {
+        let mut out = ProdNode::new();
+        let mut a = ProdNode::new();
+        let mut b = ProdNode::new();
+        let mut node = WeightedSumNode::new(&mut [&mut out, &mut a, &mut b], vec![2., -3.]);
+
+        set_variable_message(&mut out, Gaussian { mu: 0., sigma: f64::INFINITY });
+        set_variable_message(&mut a, Gaussian { mu: 5., sigma: 1. });
+        set_variable_message(&mut b, Gaussian { mu: 1., sigma: 2. });
+        node.infer();
+
+        let ans = variable_message(&out);
+        assert!(approx_eq!(f64, ans.mu, 7., epsilon = 1e-9));
+        assert!(approx_eq!(f64, ans.sigma, 40f64.sqrt(), epsilon = 1e-9));
+    }
+
+    #[test]
+    fn weighted_sum_backward() {
+        let mut out = ProdNode::new();
+        let mut a = ProdNode::new();
+        let mut b = ProdNode::new();
+        let mut c = ProdNode::new();
+        let mut node = WeightedSumNode::new(&mut [&mut out, &mut a, &mut b, &mut c], vec![2., -3., 0.]);
+
+        set_variable_message(&mut out, Gaussian { mu: 10., sigma: 3. });
+        set_variable_message(&mut a, Gaussian { mu: 5., sigma: 1. });
+        set_variable_message(&mut b, Gaussian { mu: 1., sigma: 2. });
+        set_variable_message(&mut c, Gaussian { mu: 4., sigma: 1. });
+        node.infer();
+
+        // a = (out + 3b) / 2
+        let ans = variable_message(&a);
+        assert!(approx_eq!(f64, ans.mu, 6.5, epsilon = 1e-9));
+        assert!(approx_eq!(f64, ans.sigma, (9. + 36f64).sqrt() / 2., epsilon = 1e-9));
+
+        // b = (2a - out) / 3
+        let ans = variable_message(&b);
+        assert!(approx_eq!(f64, ans.mu, 0., epsilon = 1e-9));
+        assert!(approx_eq!(f64, ans.sigma, (4. + 9f64).sqrt() / 3., epsilon = 1e-9));
+
+        assert!(RefCell::borrow(c.get_edges().last().unwrap()).0.is_uniform());
+    }
+
+    #[test]
+    fn weighted_sum_offset_backward() {
+        let mut out = ProdNode::new();
+        let mut a = ProdNode::new();
+        let mut b = ProdNode::new();
+        let mut c = ProdNode::new();
+        let mut node = WeightedSumNode::new(&mut [&mut out, &mut a, &mut b, &mut c], vec![2., -3., 0.])
+            .with_offset(-4.);
+
+        set_variable_message(&mut out, Gaussian { mu: 6., sigma: 3. });
+        set_variable_message(&mut a, Gaussian { mu: 5., sigma: 1. });
+        set_variable_message(&mut b, Gaussian { mu: 1., sigma: 2. });
+        set_variable_message(&mut c, Gaussian { mu: 4., sigma: 1. });
+        node.infer();
+
+        // a = (out + 4 + 3b) / 2
+        let ans = variable_message(&a);
+        assert!(approx_eq!(f64, ans.mu, 6.5, epsilon = 1e-9));
+        assert!(approx_eq!(f64, ans.sigma, (9. + 36f64).sqrt() / 2., epsilon = 1e-9));
+
+        // b = (2a - 4 - out) / 3
+        let ans = variable_message(&b);
+        assert!(approx_eq!(f64, ans.mu, 0., epsilon = 1e-9));
+        assert!(approx_eq!(f64, ans.sigma, (4. + 9f64).sqrt() / 3., epsilon = 1e-9));
+
+        assert!(RefCell::borrow(c.get_edges().last().unwrap()).0.is_uniform());
+    }
+
+    #[test]
+    fn weighted_sum_before_summands() {
+        let mut out = ProdNode::new();
+        let mut a = ProdNode::new();
+        let mut node = WeightedSumNode::new(&mut [&mut out, &mut a], vec![1.]).with_offset(3.);
+
+        node.infer();
+        assert!(RefCell::borrow(out.get_edges().last().unwrap()).0.is_uniform());
+        assert!(RefCell::borrow(a.get_edges().last().unwrap()).0.is_uniform());
+
+        set_variable_message(&mut a, Gaussian { mu: 2., sigma: 1. });
+        node.infer();
+        let ans = variable_message(&out);
+        assert!(approx_eq!(f64, ans.mu, 5., epsilon = 1e-9));
+        assert!(approx_eq!(f64, ans.sigma, 1., epsilon = 1e-9));
+    }
+
+    #[test]
+    fn weighted_sum_matches_sum() {
+        let mut nodes = vec![ProdNode::new(); 8];
+        let (weighted, plain) = nodes.split_at_mut(4);
+        let mut weighted_node = WeightedSumNode::new(
+            &mut weighted.iter_mut().map(|x| x as &mut dyn ValueNode).collect::<Vec<_>>(), vec![1.; 3]);
+        let mut plain_node = SumNode::new(
+            &mut plain.iter_mut().map(|x| x as &mut dyn ValueNode).collect::<Vec<_>>());
+
+        for (i, node) in nodes.iter_mut().enumerate() {
+            set_variable_message(node, Gaussian { mu: (i % 4) as f64, sigma: 1. + (i % 4) as f64 });
+        }
+        weighted_node.infer();
+        plain_node.infer();
+
+        for i in 0..4 {
+            let a = variable_message(&nodes[i]);
+            let b = variable_message(&nodes[i + 4]);
+            assert!(approx_eq!(f64, a.mu, b.mu, epsilon = 1e-9));
+            assert!(approx_eq!(f64, a.sigma, b.sigma, epsilon = 1e-9));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Mixture weight")]
+    fn mixture_invalid_probability() {
+        let mut out = ProdNode::new();
+        let mut summand = ProdNode::new();
+        MixtureSumNode::new(&mut out, &mut summand, vec![
+            (1.5, Gaussian { mu: 0., sigma: 1. }),
+            (-0.5, Gaussian { mu: 0., sigma: 10. }),
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "non-positive sigma")]
+    fn mixture_invalid_scale() {
+        let mut out = ProdNode::new();
+        let mut summand = ProdNode::new();
+        MixtureSumNode::new(&mut out, &mut summand, vec![
+            (0.9, Gaussian { mu: 0., sigma: 1. }),
+            (0.1, Gaussian { mu: 0., sigma: 0. }),
+        ]);
+    }
+}
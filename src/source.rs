@@ -0,0 +1,811 @@
+use crate::{Contest, ContestInfo, ContestPlace, Player, PriorProvider, Team, codeforces_prior};
+use read_codeforces::Contest as EbTechContest;
+use read_codeforces::{Client, Issue, read_contest};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+
+/// Contest together with its metadata
+#[derive(Clone, Debug)]
+pub struct ContestRecord {
+    pub id: usize,
+    pub name: String,
+    /// Time of the contest in seconds
+    pub when: usize,
+    pub contest: Contest,
+    /// Statuses, frozen players and biases, empty if the source has none
+    pub info: ContestInfo,
+    /// Codeforces rating of every participant before the contest, empty for other sources
+    pub old_ratings: HashMap<Player, i32>,
+    /// Anomalies fixed while reading the contest, to be reported by the caller
+    pub issues: Vec<Issue>,
+}
+
+/// Seeds every player by `codeforces_prior` from their rating before the first of `records` they took part in
+pub fn codeforces_priors<'a>(records: impl IntoIterator<Item = &'a ContestRecord>) -> PriorProvider {
+    let mut first = HashMap::new();
+    for record in records {
+        for (player, rating) in &record.old_ratings {
+            first.entry(player.clone()).or_insert((*rating, record.when));
+        }
+    }
+
+    PriorProvider::new(move |player| first.get(player).and_then(|&(rating, when)| codeforces_prior(rating, when)))
+}
+
+/// Origin of the contests to replay
+pub trait ContestSource {
+    /// Contests in chronological order
+    fn contests(&self) -> Box<dyn Iterator<Item = Result<ContestRecord, String>> + '_>;
+}
+
+impl<S: ContestSource + ?Sized> ContestSource for Box<S> {
+    fn contests(&self) -> Box<dyn Iterator<Item = Result<ContestRecord, String>> + '_> {
+        (**self).contests()
+    }
+}
+
+
+/// Converts standings given as `(player, lo, hi)`, where `lo..=hi` are the 0-based positions shared by
+/// the tied players, into a `Contest` of single-player teams.
+/// Fails unless the ranges exactly cover the rows in order.
+pub fn contest_from_ranges(standings: &[(Player, usize, usize)]) -> Result<Contest, String> {
+    let mut contest = Contest::new();
+    let mut seen = HashSet::with_capacity(standings.len());
+    let mut i = 0;
+
+    while i < standings.len() {
+        let (_, lo, hi) = standings[i];
+        if lo != i || hi < lo || hi >= standings.len() {
+            return Err(format!("Invalid range {}..={} at position {}", lo, hi, i));
+        }
+
+        let mut place = ContestPlace::with_capacity(hi - lo + 1);
+        for (j, (player, player_lo, player_hi)) in standings[lo..=hi].iter().enumerate() {
+            if (*player_lo, *player_hi) != (lo, hi) {
+                return Err(format!("Range {}..={} at position {} intersects {}..={}",
+                                   player_lo, player_hi, lo + j, lo, hi));
+            }
+            if !seen.insert(player) {
+                return Err(format!("Duplicate player {}", player));
+            }
+            place.push(vec![player.clone()]);
+        }
+
+        contest.push(place);
+        i = hi + 1;
+    }
+
+    Ok(contest)
+}
+
+
+/// Merges the members of every team into the place of the best of them.
+/// Members absent from `contest` are skipped.
+fn merge_teams(contest: Contest, teams: &[Team]) -> Contest {
+    let mut team_of = HashMap::new();
+    for (i, team) in teams.iter().enumerate() {
+        for member in team {
+            team_of.insert(member, i);
+        }
+    }
+    let present: HashSet<&Player> = contest.iter().flatten().flatten().collect();
+    let mut placed = HashSet::new();
+    let mut ans = Contest::with_capacity(contest.len());
+
+    for place in &contest {
+        let mut new_place = ContestPlace::new();
+
+        for player in place.iter().flatten() {
+            match team_of.get(player) {
+                Some(i) if !placed.insert(*i) => {}
+                Some(i) => new_place.push(
+                    teams[*i].iter().filter(|member| present.contains(member)).cloned().collect()),
+                None => new_place.push(vec![player.clone()]),
+            }
+        }
+
+        if !new_place.is_empty() {
+            ans.push(new_place);
+        }
+    }
+
+    ans
+}
+
+
+impl TryFrom<&EbTechContest> for ContestRecord {
+    type Error = String;
+
+    fn try_from(from: &EbTechContest) -> Result<Self, Self::Error> {
+        let contest = contest_from_ranges(&from.standings)
+            .map_err(|err| format!("Contest {}: {}", from.id, err))?;
+
+        Ok(ContestRecord {
+            id: from.id,
+            name: from.name.clone(),
+            when: from.time_seconds,
+            contest: merge_teams(contest, &from.teams),
+            info: ContestInfo::default(),
+            old_ratings: from.old_ratings.clone(),
+            issues: from.issues.clone(),
+        })
+    }
+}
+
+
+/// Codeforces contests with the given ids, cached in `cache_dir`
+pub struct CodeforcesSource {
+    pub cache_dir: PathBuf,
+    pub ids: Vec<usize>,
+    /// Fetch the standings to rate team members together
+    pub teams: bool,
+    pub client: Client,
+}
+
+impl CodeforcesSource {
+    pub fn new(cache_dir: impl Into<PathBuf>, ids: Vec<usize>, client: Client) -> Self {
+        CodeforcesSource { cache_dir: cache_dir.into(), ids, teams: false, client }
+    }
+
+    pub fn with_teams(mut self) -> Self {
+        self.teams = true;
+        self
+    }
+}
+
+impl ContestSource for CodeforcesSource {
+    fn contests(&self) -> Box<dyn Iterator<Item = Result<ContestRecord, String>> + '_> {
+        Box::new(self.ids.iter().map(move |id| {
+            let contest = if self.teams {
+                self.client.contest_with_teams(&self.cache_dir, *id)
+            } else {
+                self.client.contest(&self.cache_dir, *id)
+            };
+
+            ContestRecord::try_from(&contest.map_err(|err| err.to_string())?)
+        }))
+    }
+}
+
+
+/// All `*.json` files of a directory in the cache format, ordered by time
+pub struct DirectorySource {
+    pub dir: PathBuf,
+}
+
+impl DirectorySource {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        DirectorySource { dir: dir.into() }
+    }
+}
+
+impl ContestSource for DirectorySource {
+    fn contests(&self) -> Box<dyn Iterator<Item = Result<ContestRecord, String>> + '_> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                let err = format!("Failed to read {}: {}", self.dir.display(), err);
+                return Box::new(std::iter::once(Err(err)));
+            }
+        };
+
+        let mut contests = Vec::new();
+        let mut errors = Vec::new();
+
+        for entry in entries {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(err) => {
+                    errors.push(Err(err.to_string()));
+                    continue;
+                }
+            };
+            if path.extension().is_none_or(|ext| ext != "json") {
+                continue;
+            }
+
+            match read_contest(&path).map_err(|err| err.to_string()) {
+                Ok(contest) => match ContestRecord::try_from(&contest) {
+                    Ok(contest) => contests.push(contest),
+                    Err(err) => errors.push(Err(err)),
+                },
+                Err(err) => errors.push(Err(err)),
+            }
+        }
+
+        contests.sort_by_key(|contest| (contest.when, contest.id));
+
+        Box::new(errors.into_iter().chain(contests.into_iter().map(Ok)))
+    }
+}
+
+
+/// Contests kept in memory, e.g. built by hand or generated in tests
+#[derive(Clone, Debug, Default)]
+pub struct InMemorySource {
+    pub contests: Vec<ContestRecord>,
+}
+
+impl ContestSource for InMemorySource {
+    fn contests(&self) -> Box<dyn Iterator<Item = Result<ContestRecord, String>> + '_> {
+        Box::new(self.contests.iter().cloned().map(Ok))
+    }
+}
+
+
+/// Rejects empty places and teams, players taking part more than once
+/// and the info about players not taking part
+fn validate_contest(id: usize, contest: &Contest, info: &ContestInfo) -> Result<(), String> {
+    let mut seen = HashMap::new();
+
+    for (i, place) in contest.iter().enumerate() {
+        if place.is_empty() {
+            return Err(format!("Contest {}: place {} is empty", id, i));
+        }
+        for team in place {
+            if team.is_empty() {
+                return Err(format!("Contest {}: empty team at place {}", id, i));
+            }
+            for player in team {
+                if let Some(j) = seen.insert(player, i) {
+                    return Err(format!("Contest {}: player {:?} is at places {} and {}", id, player, j, i));
+                }
+            }
+        }
+    }
+
+    let players = info.status.keys().chain(&info.frozen).chain(info.player_bias.keys());
+    for player in players {
+        if !seen.contains_key(player) {
+            return Err(format!("Contest {}: player {:?} does not take part", id, player));
+        }
+    }
+
+    Ok(())
+}
+
+
+const CSV_HEADER: [&str; 6] = ["contest", "name", "time", "place", "team", "player"];
+/// Columns which may follow `CSV_HEADER` in any order
+const CSV_OPTIONAL: [&str; 3] = ["status", "frozen", "bias"];
+
+struct CsvContest {
+    name: String,
+    when: usize,
+    /// Named teams of every place in the order of appearance
+    places: BTreeMap<usize, Vec<(String, Team)>>,
+    info: ContestInfo,
+}
+
+/// Parses contests in CSV, one participant per row, see README for the format
+pub fn parse_csv(text: &str) -> Result<Vec<ContestRecord>, String> {
+    let mut contests: BTreeMap<usize, CsvContest> = BTreeMap::new();
+
+    let header: Vec<&str> = text.lines().next().unwrap_or_default().split(',').map(str::trim).collect();
+    let optional = header.get(CSV_HEADER.len()..).unwrap_or_default();
+    if header[..header.len().min(CSV_HEADER.len())] != CSV_HEADER
+        || optional.iter().any(|column| !CSV_OPTIONAL.contains(column))
+        || optional.iter().collect::<HashSet<_>>().len() != optional.len() {
+        return Err(format!("Line 1: expected header {:?} followed by some of {:?}, found {:?}",
+                           CSV_HEADER.join(","), CSV_OPTIONAL.join(","), header.join(",")));
+    }
+
+    for (i, line) in text.lines().enumerate().skip(1) {
+        let line_no = i + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        if fields.len() != header.len() {
+            return Err(format!("Line {}: expected {} fields, found {}", line_no, header.len(), fields.len()));
+        }
+        let parse = |field: &str, what: &str| field.parse::<usize>()
+            .map_err(|err| format!("Line {}: invalid {} {:?}: {}", line_no, what, field, err));
+
+        let id = parse(fields[0], "contest id")?;
+        let time = parse(fields[2], "time")?;
+        let place = parse(fields[3], "place")?;
+        let (team, player) = (fields[4], fields[5]);
+        if team.is_empty() || player.is_empty() {
+            return Err(format!("Line {}: empty team or player", line_no));
+        }
+
+        let contest = contests.entry(id).or_insert_with(|| CsvContest {
+            name: fields[1].to_string(),
+            when: time,
+            places: BTreeMap::new(),
+            info: ContestInfo::default(),
+        });
+        if contest.name != fields[1] || contest.when != time {
+            return Err(format!("Line {}: inconsistent name or time of contest {}", line_no, id));
+        }
+
+        let teams = contest.places.entry(place).or_default();
+        match teams.iter_mut().find(|(name, _)| name == team) {
+            Some((_, members)) => members.push(player.to_string()),
+            None => teams.push((team.to_string(), vec![player.to_string()])),
+        }
+
+        // empty optional fields are omitted
+        for (column, field) in optional.iter().zip(&fields[CSV_HEADER.len()..]) {
+            if field.is_empty() {
+                continue;
+            }
+            let invalid = |err: String| format!("Line {}: invalid {} {:?}: {}", line_no, column, field, err);
+            let info = &mut contest.info;
+            match *column {
+                "status" => {
+                    info.status.insert(player.to_string(), field.parse().map_err(invalid)?);
+                }
+                "frozen" => {
+                    if field.parse().map_err(|err: std::str::ParseBoolError| invalid(err.to_string()))? {
+                        info.frozen.insert(player.to_string());
+                    }
+                }
+                _ => {
+                    let bias = field.parse().map_err(|err: std::num::ParseFloatError| invalid(err.to_string()))?;
+                    info.player_bias.insert(player.to_string(), bias);
+                }
+            }
+        }
+    }
+
+    let mut ans = Vec::with_capacity(contests.len());
+
+    for (id, CsvContest { name, when, places, info }) in contests {
+        let contest: Contest = places.into_values()
+            .map(|teams| teams.into_iter().map(|(_, members)| members).collect())
+            .collect();
+
+        validate_contest(id, &contest, &info)?;
+
+        ans.push(ContestRecord { id, name, when, contest, info, old_ratings: HashMap::new(), issues: Vec::new() });
+    }
+
+    ans.sort_by_key(|contest| (contest.when, contest.id));
+
+    Ok(ans)
+}
+
+
+#[derive(Deserialize)]
+struct JsonContest {
+    id: usize,
+    name: String,
+    time: usize,
+    places: Contest,
+    #[serde(default)]
+    status: HashMap<Player, String>,
+    #[serde(default)]
+    frozen: HashSet<Player>,
+    #[serde(default)]
+    bias: HashMap<Player, f64>,
+}
+
+/// Parses contests in JSON lines, one contest per line, see README for the format
+pub fn parse_json_lines(text: &str) -> Result<Vec<ContestRecord>, String> {
+    let mut ans = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let contest: JsonContest = serde_json::from_str(line).map_err(|err| format!("Line {}: {}", i + 1, err))?;
+        let status = contest.status.into_iter()
+            .map(|(player, status)| Ok((player, status.parse()?)))
+            .collect::<Result<_, String>>()
+            .map_err(|err| format!("Line {}: {}", i + 1, err))?;
+        let info = ContestInfo { status, frozen: contest.frozen, player_bias: contest.bias, ..ContestInfo::default() };
+        validate_contest(contest.id, &contest.places, &info).map_err(|err| format!("Line {}: {}", i + 1, err))?;
+        ans.push(ContestRecord { id: contest.id, name: contest.name, when: contest.time, contest: contest.places, info, old_ratings: HashMap::new(), issues: Vec::new() });
+    }
+
+    ans.sort_by_key(|contest| (contest.when, contest.id));
+
+    Ok(ans)
+}
+
+
+/// Contests from a `.csv` or `.jsonl` file
+pub struct FileSource {
+    pub path: PathBuf,
+}
+
+impl FileSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileSource { path: path.into() }
+    }
+
+    fn read(&self) -> Result<Vec<ContestRecord>, String> {
+        let text = std::fs::read_to_string(&self.path)
+            .map_err(|err| format!("Failed to read {}: {}", self.path.display(), err))?;
+
+        match self.path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => parse_csv(&text),
+            Some("jsonl") => parse_json_lines(&text),
+            _ => Err(format!("Unknown format of {}, expected .csv or .jsonl", self.path.display())),
+        }
+        .map_err(|err| format!("{}: {}", self.path.display(), err))
+    }
+}
+
+impl ContestSource for FileSource {
+    fn contests(&self) -> Box<dyn Iterator<Item = Result<ContestRecord, String>> + '_> {
+        match self.read() {
+            Ok(contests) => Box::new(contests.into_iter().map(Ok)),
+            Err(err) => Box::new(std::iter::once(Err(err))),
+        }
+    }
+}
+
+
+/// Maps old handles of players to their current ones
+#[derive(Clone, Debug, Default)]
+pub struct Aliases {
+    current: HashMap<Player, Player>,
+}
+
+impl Aliases {
+    pub fn new() -> Self {
+        Aliases::default()
+    }
+
+    pub fn insert(&mut self, old: Player, current: Player) {
+        if old != current {
+            self.current.insert(old, current);
+        }
+    }
+
+    /// Later renames take precedence
+    pub fn extend(&mut self, other: Aliases) {
+        self.current.extend(other.current);
+    }
+
+    pub fn len(&self) -> usize {
+        self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current.is_empty()
+    }
+
+    /// The current handle, following chains of renames.
+    /// Fails if the renames of `player` form a cycle.
+    pub fn resolve<'a>(&'a self, player: &'a str) -> Result<&'a str, String> {
+        let mut ans = player;
+        // a chain without cycles has at most one rename per alias
+        for _ in 0..self.current.len() {
+            match self.current.get(ans) {
+                Some(next) => ans = next,
+                None => return Ok(ans),
+            }
+        }
+        match self.current.get(ans) {
+            Some(_) => Err(format!("Renames of player {} form a cycle", player)),
+            None => Ok(ans),
+        }
+    }
+
+    /// Parses lines `old current`, empty lines and lines starting with `#` are skipped
+    pub fn parse(text: &str) -> Result<Aliases, String> {
+        let mut aliases = Aliases::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() != 2 {
+                return Err(format!("Line {}: expected `old current`, found {:?}", i + 1, line));
+            }
+            aliases.insert(fields[0].to_string(), fields[1].to_string());
+        }
+
+        Ok(aliases)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Aliases, String> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+
+        Aliases::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Writes the aliases in the format of `parse`, sorted by the old handle
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let mut pairs: Vec<_> = self.current.iter().collect();
+        pairs.sort();
+
+        let text: String = pairs.into_iter().map(|(old, current)| format!("{} {}\n", old, current)).collect();
+        std::fs::write(path, text).map_err(|err| format!("Failed to write {}: {}", path.display(), err))
+    }
+
+    /// Asks Codeforces `user.info` for the current handles of the given players
+    pub fn fetch(client: &Client, players: &[Player]) -> Result<Aliases, String> {
+        let current = client.current_handles(players).map_err(|err| err.to_string())?;
+
+        Ok(Aliases { current })
+    }
+
+    /// Renames the players of a contest and its info to their current handles.
+    /// Fails if two participants turn out to be the same player.
+    pub fn apply(&self, mut record: ContestRecord) -> Result<ContestRecord, String> {
+        let mut seen = HashSet::new();
+        let id = record.id;
+
+        for player in record.contest.iter_mut().flatten().flatten() {
+            let current = self.resolve(player).map_err(|err| format!("Contest {}: {}", id, err))?.to_string();
+            if !seen.insert(current.clone()) {
+                return Err(format!("Contest {}: player {} appears under several handles", id, current));
+            }
+            *player = current;
+        }
+
+        let rename = |player: &Player| self.resolve(player).map(str::to_string)
+            .map_err(|err| format!("Contest {}: {}", id, err));
+        let info = &mut record.info;
+        info.status = info.status.iter().map(|(player, status)| Ok((rename(player)?, *status))).collect::<Result<_, String>>()?;
+        info.frozen = info.frozen.iter().map(rename).collect::<Result<_, String>>()?;
+        info.player_bias = info.player_bias.iter().map(|(player, bias)| Ok((rename(player)?, *bias))).collect::<Result<_, String>>()?;
+        record.old_ratings = record.old_ratings.iter()
+            .map(|(player, rating)| Ok((rename(player)?, *rating)))
+            .collect::<Result<_, String>>()?;
+
+        Ok(record)
+    }
+}
+
+
+/// Contests of `source` with the players renamed by `aliases`, so that histories merge
+pub struct AliasedSource<S> {
+    pub source: S,
+    pub aliases: Aliases,
+}
+
+impl<S: ContestSource> ContestSource for AliasedSource<S> {
+    fn contests(&self) -> Box<dyn Iterator<Item = Result<ContestRecord, String>> + '_> {
+        Box::new(self.source.contests().map(move |record| self.aliases.apply(record?)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Status;
+
+    #[test]
+    fn directory() {
+        let dir = std::env::temp_dir().join(format!("trueskill_spb_source_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        std::fs::write(dir.join("later.json"), r#"{"id": 2, "name": "Later", "time_seconds": 20,
+            "standings": [["a", 0, 0], ["b", 1, 2], ["c", 1, 2]]}"#).unwrap();
+        std::fs::write(dir.join("earlier.json"), r#"{"id": 1, "name": "Earlier", "time_seconds": 10,
+            "standings": [["b", 0, 0]]}"#).unwrap();
+        std::fs::write(dir.join("broken.json"), "{").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a contest").unwrap();
+
+        let contests: Vec<_> = DirectorySource::new(&dir).contests().collect();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(contests.len(), 3);
+        assert!(contests[0].is_err());
+
+        let earlier = contests[1].as_ref().unwrap();
+        let later = contests[2].as_ref().unwrap();
+        assert_eq!((earlier.id, earlier.when), (1, 10));
+        assert_eq!(later.name, "Later");
+        assert_eq!(later.contest, vec![vec![vec!["a".to_string()]],
+                                       vec![vec!["b".to_string()], vec!["c".to_string()]]]);
+    }
+
+    #[test]
+    fn ranges() {
+        let row = |player: &str, lo, hi| (player.to_string(), lo, hi);
+
+        assert_eq!(contest_from_ranges(&[row("a", 0, 1), row("b", 0, 1), row("c", 2, 2)]).unwrap(), vec![
+            vec![vec!["a".to_string()], vec!["b".to_string()]],
+            vec![vec!["c".to_string()]],
+        ]);
+        assert_eq!(contest_from_ranges(&[]).unwrap(), Contest::new());
+
+        assert!(contest_from_ranges(&[row("a", 0, 2), row("b", 0, 2), row("c", 2, 2)]).is_err());
+        assert!(contest_from_ranges(&[row("a", 0, 0), row("b", 0, 0)]).is_err());
+        assert!(contest_from_ranges(&[row("a", 1, 1), row("b", 0, 0)]).is_err());
+        assert!(contest_from_ranges(&[row("a", 0, 1), row("b", 0, 1), row("c", 2, 3)]).is_err());
+        assert!(contest_from_ranges(&[row("a", 0, 0), row("a", 1, 1)]).is_err());
+    }
+
+    #[test]
+    fn codeforces_teams() {
+        let contest = EbTechContest {
+            id: 1,
+            name: "Team round".to_string(),
+            time_seconds: 10,
+            standings: vec![("a".to_string(), 0, 1), ("x".to_string(), 0, 1), ("b".to_string(), 2, 2),
+                            ("c".to_string(), 3, 3)],
+            old_ratings: HashMap::new(),
+            teams: vec![vec!["a".to_string(), "b".to_string(), "unrated".to_string()]],
+            issues: Vec::new(),
+        };
+
+        assert_eq!(ContestRecord::try_from(&contest).unwrap().contest, vec![
+            vec![vec!["a".to_string(), "b".to_string()], vec!["x".to_string()]],
+            vec![vec!["c".to_string()]],
+        ]);
+    }
+
+    #[test]
+    fn csv() {
+        let text = "contest,name,time,place,team,player
+2,Team round,200,2,blue,c
+2,Team round,200,1,red,a
+2,Team round,200,1,red,b
+2,Team round,200,2,green,d
+1,Warmup,100,1,solo,a
+";
+        let contests = parse_csv(text).unwrap();
+
+        assert_eq!(contests.len(), 2);
+        assert_eq!((contests[0].id, contests[0].name.as_str(), contests[0].when), (1, "Warmup", 100));
+        assert_eq!(contests[1].contest, vec![
+            vec![vec!["a".to_string(), "b".to_string()]],
+            vec![vec!["c".to_string()], vec!["d".to_string()]],
+        ]);
+
+        let header = "contest,name,time,place,team,player\n";
+        let parse = |rows: &str| parse_csv(&(header.to_string() + rows));
+        assert!(parse("1,a,1,1,t\n").is_err());
+        assert!(parse("1,a,1,x,t,p\n").is_err());
+        assert!(parse("1,a,1,1,t,p\n1,a,2,1,t,q\n").is_err());
+        assert!(parse("1,a,1,1,t,p\n").is_ok());
+    }
+
+    #[test]
+    fn csv_invalid() {
+        let header = "contest,name,time,place,team,player\n";
+        let parse = |rows: &str| parse_csv(&(header.to_string() + rows));
+
+        assert!(parse_csv("contest,name,time,place,player,team\n1,a,1,1,t,p\n").is_err());
+        assert!(parse_csv("").is_err());
+        // the same player twice in a team, in two teams and at two places
+        assert!(parse("1,a,1,1,t,p\n1,a,1,1,t,p\n").is_err());
+        assert!(parse("1,a,1,1,t,p\n1,a,1,1,u,p\n").is_err());
+        assert!(parse("1,a,1,1,t,p\n1,a,1,2,u,p\n").is_err());
+        assert!(parse("1,a,1,1,t,\n").is_err());
+        assert!(parse("1,a,1,1,,p\n").is_err());
+    }
+
+    #[test]
+    fn csv_info() {
+        let text = "contest,name,time,place,team,player,bias,status,frozen
+1,Cup,100,1,red,a,,,true
+1,Cup,100,1,red,b,-50.5,,
+1,Cup,100,2,blue,c,,no_show,false
+";
+        let contests = parse_csv(text).unwrap();
+        let info = &contests[0].info;
+
+        assert_eq!(info.frozen, HashSet::from(["a".to_string()]));
+        assert_eq!(info.player_bias, HashMap::from([("b".to_string(), -50.5)]));
+        assert_eq!(info.status, HashMap::from([("c".to_string(), Status::NoShow)]));
+
+        let parse = |column: &str, value: &str| parse_csv(&format!("contest,name,time,place,team,player,{}\n1,a,1,1,t,p,{}\n", column, value));
+        assert!(parse("status", "disqualified").is_ok());
+        assert!(parse("status", "late").is_err());
+        assert!(parse("frozen", "yes").is_err());
+        assert!(parse("bias", "x").is_err());
+        assert!(parse("rank", "1").is_err());
+        assert!(parse("bias,bias", "1,1").is_err());
+        assert!(parse("bias", "1,1").is_err());
+    }
+
+    #[test]
+    fn json_lines() {
+        let text = r#"{"id": 7, "name": "Cup", "time": 50, "places": [[["a", "b"]], [["c"], ["d"]]]}
+
+{"id": 3, "name": "Open", "time": 10, "places": [[["x"]]]}
+"#;
+        let contests = parse_json_lines(text).unwrap();
+
+        assert_eq!(contests.len(), 2);
+        assert_eq!(contests[0].id, 3);
+        assert_eq!(contests[1].contest[1], vec![vec!["c".to_string()], vec!["d".to_string()]]);
+
+        assert!(parse_json_lines("{\"id\": 1}").is_err());
+    }
+
+    #[test]
+    fn json_lines_info() {
+        let text = r#"{"id": 1, "name": "Cup", "time": 1, "places": [[["a"]], [["b"]], [["c"]]], "status": {"c": "disqualified"}, "frozen": ["a"], "bias": {"b": 20}}"#;
+        let info = &parse_json_lines(text).unwrap()[0].info;
+
+        assert_eq!(info.status, HashMap::from([("c".to_string(), Status::Disqualified)]));
+        assert_eq!(info.frozen, HashSet::from(["a".to_string()]));
+        assert_eq!(info.player_bias, HashMap::from([("b".to_string(), 20.)]));
+
+        let parse = |info: &str| parse_json_lines(&format!(r#"{{"id": 1, "name": "a", "time": 1, "places": [[["a"]]], {}}}"#, info));
+        assert!(parse(r#""status": {"a": "late"}"#).is_err());
+        assert!(parse(r#""frozen": ["z"]"#).is_err());
+        assert!(parse(r#""bias": {"z": 1}"#).is_err());
+    }
+
+    #[test]
+    fn json_lines_invalid() {
+        let parse = |places: &str| parse_json_lines(&format!(r#"{{"id": 1, "name": "a", "time": 1, "places": {}}}"#, places));
+
+        assert!(parse(r#"[[["a"]]]"#).is_ok());
+        assert!(parse(r#"[[["a"]], []]"#).is_err());
+        assert!(parse(r#"[[["a"], []]]"#).is_err());
+        assert!(parse(r#"[[["a", "a"]]]"#).is_err());
+        assert!(parse(r#"[[["a"], ["a", "b"]]]"#).is_err());
+        assert!(parse(r#"[[["a"]], [["a"]]]"#).is_err());
+    }
+
+    #[test]
+    fn aliases() {
+        let aliases = Aliases::parse("# renames\nalice alicia\n\nalicia ally\nbob carol\n").unwrap();
+        assert_eq!(aliases.len(), 3);
+        assert_eq!(aliases.resolve("alice"), Ok("ally"));
+        assert_eq!(aliases.resolve("dave"), Ok("dave"));
+        assert!(Aliases::parse("alice\n").is_err());
+
+        let source = AliasedSource {
+            source: InMemorySource {
+                contests: vec![
+                    ContestRecord { id: 1, name: "A".into(), when: 1, contest: vec![vec![vec!["alice".into(), "dave".into()]]],
+                                    info: ContestInfo { frozen: HashSet::from(["alice".into()]), ..ContestInfo::default() }, old_ratings: HashMap::new(), issues: Vec::new() },
+                    ContestRecord { id: 2, name: "B".into(), when: 2, contest: vec![vec![vec!["ally".into()]], vec![vec!["alicia".into()]]], info: ContestInfo::default(), old_ratings: HashMap::new(), issues: Vec::new() },
+                ],
+            },
+            aliases,
+        };
+        let contests: Vec<_> = source.contests().collect();
+
+        assert_eq!(contests[0].as_ref().unwrap().contest, vec![vec![vec!["ally".to_string(), "dave".to_string()]]]);
+        assert_eq!(contests[0].as_ref().unwrap().info.frozen, HashSet::from(["ally".to_string()]));
+        assert!(contests[1].is_err());
+    }
+
+    #[test]
+    fn priors() {
+        let record = |when: usize, old_ratings: &[(&str, i32)]| ContestRecord {
+            id: when,
+            name: "A".into(),
+            when,
+            contest: Vec::new(),
+            info: ContestInfo::default(),
+            old_ratings: old_ratings.iter().map(|&(player, rating)| (player.to_string(), rating)).collect(),
+            issues: Vec::new(),
+        };
+        let records = vec![record(1, &[("a", 1800)]), record(2, &[("a", 2000), ("b", 1500)])];
+
+        let PriorProvider(prior) = codeforces_priors(&records);
+        assert_eq!(prior(&"a".to_string()).unwrap().mu, 1800.);
+        assert!(prior(&"b".to_string()).is_none());
+        assert!(prior(&"c".to_string()).is_none());
+    }
+
+    #[test]
+    fn cyclic_aliases() {
+        let aliases = Aliases::parse("a b\nb a\nc a\nd e\n").unwrap();
+        assert!(aliases.resolve("a").is_err());
+        assert!(aliases.resolve("c").is_err());
+        assert_eq!(aliases.resolve("d"), Ok("e"));
+
+        let source = AliasedSource {
+            source: InMemorySource { contests: vec![ContestRecord { id: 1, name: "A".into(), when: 1, contest: vec![vec![vec!["b".into()]]], info: ContestInfo::default(), old_ratings: HashMap::new(), issues: Vec::new() }] },
+            aliases,
+        };
+        assert!(source.contests().next().unwrap().is_err());
+    }
+}